@@ -0,0 +1,1074 @@
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_util::future::join_all;
+use gateway::{parse_url, Endpoint, ServiceResult};
+use tokio::sync::Semaphore;
+
+use crate::interceptor::intercept_response;
+use crate::single_flight::Fetched;
+use crate::{
+    build_path, display_url, into_response_result, into_service_result, invalid_payload,
+    prepare_request, process_bytes_response, process_raw_response, process_response,
+    request_failed, resolve, retry_after, serialize_payload, Auth, Body, CappedBody, Error,
+    FormPart, Options, Prepared, Request, RequestContext, ReqwestJsonServiceBuilder, Response,
+    ResponseResult, Retry, Route, Source,
+};
+
+/// Service implementation using Reqwest's async client for proxying to the backing api(s)
+/// from within a running async executor
+pub struct AsyncReqwestJsonService {
+    url: url::Url,
+    client: reqwest::Client,
+    options: Options,
+    limiter: Option<Arc<Semaphore>>,
+}
+
+impl fmt::Debug for AsyncReqwestJsonService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AsyncReqwestJsonService {{ url: {} }}", self.url)
+    }
+}
+
+impl AsyncReqwestJsonService {
+    pub(crate) fn from_parts(url: url::Url, client: reqwest::Client, options: Options) -> Self {
+        AsyncReqwestJsonService {
+            url,
+            // The semaphore can't hold more permits, which is no cap in practice anyway
+            limiter: options
+                .max_concurrent
+                .map(|max| Arc::new(Semaphore::new(max.min(Semaphore::MAX_PERMITS)))),
+            client,
+            options,
+        }
+    }
+
+    pub fn with_url(url_str: &str) -> Result<Self, gateway::Error> {
+        parse_url(url_str).map(|url| {
+            AsyncReqwestJsonService::from_parts(url, reqwest::Client::new(), Options::default())
+        })
+    }
+
+    /// Send requests with the provided client, see `ReqwestJsonService::with_url_and_client`
+    pub fn with_url_and_client(url_str: &str, client: reqwest::Client) -> Result<Self, gateway::Error> {
+        parse_url(url_str).map(|url| AsyncReqwestJsonService::from_parts(url, client, Options::default()))
+    }
+
+    /// Fail any request which takes longer than `timeout` to complete instead of waiting on
+    /// an unresponsive backing api, surfacing the timeout through `Error::TimedOut`
+    pub fn with_url_and_timeout(url_str: &str, timeout: Duration) -> Result<Self, Error> {
+        ReqwestJsonServiceBuilder::new()
+            .url(url_str)
+            .timeout(timeout)
+            .build_async()
+    }
+
+    /// Configure a service beyond what the `with_url` shortcuts allow, see `build_async`
+    pub fn builder() -> ReqwestJsonServiceBuilder {
+        ReqwestJsonServiceBuilder::new()
+    }
+
+    /// Authorize every request with an `Authorization: Bearer <token>` header
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.options.auth = Some(Auth::Bearer(token.to_owned()));
+        self
+    }
+
+    /// Authorize every request with http basic auth credentials
+    pub fn basic_auth(mut self, username: &str, password: Option<&str>) -> Self {
+        self.options.auth = Some(Auth::Basic {
+            username: username.to_owned(),
+            password: password.map(str::to_owned),
+        });
+        self
+    }
+
+    /// Re-attempt requests up to `max` additional times when the backing api is unreachable or
+    /// responds with a 5xx or 429, waiting `base_delay` doubled for each consecutive retry in
+    /// between unless the response requests a `Retry-After`
+    pub fn with_retries(mut self, max: u32, base_delay: Duration) -> Self {
+        self.options.retry = Retry {
+            max,
+            base_delay,
+            ..self.options.retry
+        };
+        self
+    }
+
+    /// Execute the request as `exec` does but retain the http context of a successful response
+    pub async fn exec_response<TRequest>(
+        &self,
+        req: TRequest,
+    ) -> ResponseResult<TRequest::TResponse, TRequest::TError>
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+    {
+        self.exec_timed(req).await.0
+    }
+
+    /// Execute the request as `exec_response` does along with the time taken by the http
+    /// exchange, from sending the request through reading the body but excluding deserialization
+    pub async fn exec_timed<TRequest>(
+        &self,
+        req: TRequest,
+    ) -> (ResponseResult<TRequest::TResponse, TRequest::TError>, Duration)
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+    {
+        self.exchange(req.into()).await
+    }
+
+    /// Execute the request as `exec` does unless `cancel` completes first, e.g. a shutdown signal
+    /// such as `CancellationToken::cancelled()`, in which case the in flight request is dropped and
+    /// the result fails with `Error::Cancelled`
+    pub async fn exec_cancellable<TRequest, TCancel>(
+        &self,
+        req: TRequest,
+        cancel: TCancel,
+    ) -> ServiceResult<TRequest, Error, serde_json::Error>
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+        TCancel: Future<Output = ()>,
+    {
+        tokio::select! {
+            result = self.exec(req) => result,
+            _ = cancel => ServiceResult::Fail(Error::Cancelled, None),
+        }
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::exec_route`
+    pub async fn exec_route<TRoute>(&self, route: TRoute) -> ServiceResult<TRoute, Error, serde_json::Error>
+    where
+        TRoute: Route + Endpoint,
+    {
+        match route.into_request() {
+            Ok(req) => into_service_result(self.exchange(req).await.0),
+            Err(err) => ServiceResult::Fail(err, None),
+        }
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::exec_post_json`
+    pub async fn exec_post_json<TBody, TResponse, TError>(
+        &self,
+        path: &str,
+        body: &TBody,
+    ) -> ResponseResult<TResponse, TError>
+    where
+        TBody: serde::Serialize,
+        TResponse: serde::de::DeserializeOwned + fmt::Debug,
+        TError: serde::de::DeserializeOwned + fmt::Debug,
+    {
+        match serialize_payload(body) {
+            Ok(body) => self.exchange(Request::Post { path: path.to_owned(), body }).await.0,
+            Err(err) => ResponseResult::Fail(err, None),
+        }
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::exec_put_json`
+    pub async fn exec_put_json<TBody, TResponse, TError>(
+        &self,
+        path: &str,
+        body: &TBody,
+    ) -> ResponseResult<TResponse, TError>
+    where
+        TBody: serde::Serialize,
+        TResponse: serde::de::DeserializeOwned + fmt::Debug,
+        TError: serde::de::DeserializeOwned + fmt::Debug,
+    {
+        match serialize_payload(body) {
+            Ok(body) => self.exchange(Request::Put { path: path.to_owned(), body }).await.0,
+            Err(err) => ResponseResult::Fail(err, None),
+        }
+    }
+
+    async fn exchange<TResponse, TError>(&self, req: Request) -> (ResponseResult<TResponse, TError>, Duration)
+    where
+        TResponse: serde::de::DeserializeOwned + fmt::Debug,
+        TError: serde::de::DeserializeOwned + fmt::Debug,
+    {
+        let expect = req.expectations();
+        let method = req.method();
+
+        // Call the service and pull out the body text
+        let started = Instant::now();
+        let exchange = self.fetch(req).await;
+        let elapsed = started.elapsed();
+        let result = match exchange {
+            Ok((url, status, headers, text)) => {
+                let text = self.options.revalidate(&method, &url, status, &headers, text);
+                let (status, headers, text) =
+                    intercept_response(&self.options, status, headers, text);
+                process_response(&self.options, url, status, expect, headers, text)
+            }
+            Err(err) => Err((err, None)),
+        };
+        (into_response_result(result), elapsed)
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::fetch`
+    async fn fetch(&self, req: Request) -> Result<Fetched, Error> {
+        let prepared = prepare_request(&self.url, &self.options, req)
+            .map(|prepared| self.options.condition(prepared));
+        let flight = match &prepared {
+            Ok(prepared) => self.options.flight_key(prepared),
+            Err(_) => None,
+        };
+        let fetch = move || async move {
+            let resp = exec_prepared(self, prepared).await?;
+            let url = display_url(resp.url().as_str());
+            let status = resp.status();
+            let headers = resp.headers().clone();
+            let text = extract_text(resp, self.options.max_response_bytes).await;
+            Ok((url, status, headers, text))
+        };
+        match flight {
+            Some((flights, key)) => flights.share_async(key, fetch).await,
+            None => fetch().await,
+        }
+    }
+
+    /// Non-blocking equivalent of `Service::exec`
+    pub async fn exec<TRequest>(&self, req: TRequest) -> ServiceResult<TRequest, Error, serde_json::Error>
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+    {
+        into_service_result(self.exec_response(req).await)
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::exec_with_context`
+    pub async fn exec_with_context<TRequest>(
+        &self,
+        req: TRequest,
+        ctx: RequestContext,
+    ) -> ServiceResult<TRequest, Error, serde_json::Error>
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+    {
+        let req = ctx.apply(&self.options, req.into());
+        into_service_result(self.exchange(req).await.0)
+    }
+
+    /// Execute independent requests concurrently over the shared client, within any configured
+    /// concurrency cap, returning their results in the same order as the requests
+    pub async fn exec_batch<TRequest>(
+        &self,
+        reqs: Vec<TRequest>,
+    ) -> Vec<ServiceResult<TRequest, Error, serde_json::Error>>
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+    {
+        join_all(reqs.into_iter().map(|req| self.exec(req))).await
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::exec_value`
+    pub async fn exec_value<TRequest>(
+        &self,
+        req: TRequest,
+    ) -> ResponseResult<serde_json::Value, serde_json::Value>
+    where
+        TRequest: Into<Request>,
+    {
+        self.exchange(req.into()).await.0
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::exec_raw`
+    pub async fn exec_raw<TRequest>(&self, req: TRequest) -> ResponseResult<String, TRequest::TError>
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+    {
+        let req = req.into();
+        let expect = req.expectations();
+        let method = req.method();
+
+        let result = match self.fetch(req).await {
+            Ok((url, status, headers, text)) => {
+                let text = self.options.revalidate(&method, &url, status, &headers, text);
+                let (status, headers, text) =
+                    intercept_response(&self.options, status, headers, text);
+                process_raw_response(&self.options, url, status, expect, headers, text)
+            }
+            Err(err) => Err((err, None)),
+        };
+        into_response_result(result)
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::exec_bytes`
+    pub async fn exec_bytes<TRequest>(&self, req: TRequest) -> ResponseResult<Vec<u8>, TRequest::TError>
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+    {
+        let req = req.into();
+        let expect = req.expectations();
+
+        let result = match exec_request(self, req).await {
+            Ok(resp) => {
+                let url = display_url(resp.url().as_str());
+                let status = resp.status();
+                let headers = resp.headers().clone();
+                let bytes = extract_bytes(resp, self.options.max_response_bytes).await;
+                process_bytes_response(&self.options, url, status, expect, headers, bytes)
+            }
+            Err(err) => Err((err, None)),
+        };
+        into_response_result(result)
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::exec_raw_response`
+    pub async fn exec_raw_response<TRequest>(&self, req: TRequest) -> Result<reqwest::Response, Error>
+    where
+        TRequest: Into<Request>,
+    {
+        exec_request(self, req.into()).await
+    }
+
+    /// Non-blocking equivalent of `ReqwestJsonService::health_check`
+    pub async fn health_check(&self, path: &str) -> Result<bool, Error> {
+        let req = Request::Get {
+            path: path.to_owned(),
+        };
+        exec_request(self, req).await.map(|resp| resp.status().is_success())
+    }
+
+    /// Base url requests are resolved against, see `ReqwestJsonService::base_url`
+    pub fn base_url(&self) -> &url::Url {
+        &self.url
+    }
+
+    /// Service for the api under the subpath of the base url, see `ReqwestJsonService::scoped`
+    pub fn scoped(&self, subpath: &str) -> Result<AsyncReqwestJsonService, Error> {
+        Ok(AsyncReqwestJsonService {
+            url: build_path(self.url.to_owned(), subpath.to_owned())?,
+            client: self.client.clone(),
+            options: self.options.clone(),
+            limiter: self.limiter.clone(),
+        })
+    }
+
+    /// Url the request would be sent to, see `ReqwestJsonService::resolve_url`
+    pub fn resolve_url(&self, req: &Request) -> Result<url::Url, Error> {
+        resolve(self.url.to_owned(), req)
+    }
+
+    /// Execute the request exposing only the status and headers of the response without reading
+    /// the body, any status is returned as is so e.g. a `HEAD` probe can check for a 404
+    pub async fn inspect<TRequest>(&self, req: TRequest) -> Result<Response<()>, Error>
+    where
+        TRequest: Into<Request>,
+    {
+        exec_request(self, req.into()).await.map(|resp| Response {
+            status: resp.status().as_u16(),
+            headers: resp.headers().clone(),
+            body: (),
+            text: String::new(),
+            length: 0,
+        })
+    }
+
+    /// Execute the request returning the body of a successful response as a stream of chunks as
+    /// they arrive instead of buffering it, e.g. for newline delimited json or server-sent
+    /// events, a failed status is reported as `Error::ResultFailed` once its body is read, cut
+    /// off at any `max_response_bytes`
+    pub async fn exec_stream<TRequest>(&self, req: TRequest) -> Result<Response<BodyStream>, Error>
+    where
+        TRequest: Into<Request>,
+    {
+        let req = req.into();
+        let expect = req.expectations();
+        let resp = exec_request(self, req).await?;
+        let status = resp.status();
+        if !self.options.accepts_with(status, expect.is_success) {
+            let url = display_url(resp.url().as_str());
+            let payload = extract_payload(resp, self.options.max_response_bytes).await?;
+            return Err(if expect.not_found && status == reqwest::StatusCode::NOT_FOUND {
+                Error::NotFound { url, payload }
+            } else {
+                Error::ResultFailed {
+                    url,
+                    status: status.as_u16(),
+                    payload,
+                    message: self.options.status_message(status.as_u16()),
+                }
+            });
+        }
+        Ok(Response {
+            status: status.as_u16(),
+            headers: resp.headers().clone(),
+            body: BodyStream(Box::pin(resp.bytes_stream())),
+            text: String::new(),
+            length: 0,
+        })
+    }
+
+    /// Execute the request as `exec_stream` does but deserialize the body as newline delimited
+    /// json, yielding each line as the endpoint's response type as soon as it has arrived so
+    /// large result sets needn't be buffered, where blank lines are skipped
+    pub async fn exec_ndjson<TRequest>(
+        &self,
+        req: TRequest,
+    ) -> Result<Response<NdjsonStream<TRequest::TResponse>>, Error>
+    where
+        TRequest: Into<Request> + Endpoint,
+    {
+        let response = self.exec_stream(req).await?;
+        let content_type = response
+            .headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        Ok(Response {
+            status: response.status,
+            headers: response.headers,
+            body: NdjsonStream {
+                chunks: response.body,
+                buffer: Vec::new(),
+                finished: false,
+                content_type,
+                items: PhantomData,
+            },
+            text: String::new(),
+            length: 0,
+        })
+    }
+}
+
+/// Chunks of a response body in the order they arrive, see `AsyncReqwestJsonService::exec_stream`
+pub struct BodyStream(Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>);
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BodyStream")
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0
+            .as_mut()
+            .poll_next(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map_err(Error::ReadBodyFailed)))
+    }
+}
+
+/// Items of a newline delimited json body, parsed a line at a time as it arrives, see
+/// `AsyncReqwestJsonService::exec_ndjson`
+///
+/// A line which fails to parse is reported as `Error::InvalidPayload` without ending the stream,
+/// while a failure to read the body ends it.
+pub struct NdjsonStream<T> {
+    chunks: BodyStream,
+    /// Bytes received which don't yet complete a line
+    buffer: Vec<u8>,
+    finished: bool,
+    content_type: Option<String>,
+    items: PhantomData<fn() -> T>,
+}
+
+impl<T> NdjsonStream<T> {
+    /// Next complete line of the body, or whatever remains once the body has been read
+    fn next_line(&mut self) -> Option<Vec<u8>> {
+        match self.buffer.iter().position(|byte| *byte == b'\n') {
+            Some(end) => Some(self.buffer.drain(..=end).collect()),
+            None if self.finished && !self.buffer.is_empty() => Some(mem::take(&mut self.buffer)),
+            None => None,
+        }
+    }
+}
+
+impl<T> fmt::Debug for NdjsonStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NdjsonStream")
+    }
+}
+
+impl<T> Stream for NdjsonStream<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    type Item = Result<T, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(line) = this.next_line() {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let item = serde_json::from_slice(&line).map_err(|serde_error| {
+                    let text = String::from_utf8_lossy(&line);
+                    invalid_payload(serde_error, text.trim_end(), this.content_type.as_deref())
+                });
+                return Poll::Ready(Some(item));
+            }
+            if this.finished {
+                return Poll::Ready(None);
+            }
+            match Pin::new(&mut this.chunks).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => this.buffer.extend_from_slice(&chunk),
+                Poll::Ready(Some(Err(err))) => {
+                    // A partial line is of no use once the body can't be read to its end
+                    this.finished = true;
+                    this.buffer.clear();
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(None) => this.finished = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+fn multipart_form(parts: Vec<FormPart>) -> Result<reqwest::multipart::Form, Error> {
+    let mut form = reqwest::multipart::Form::new();
+    for part in parts {
+        form = match part {
+            FormPart::Text { name, value } => form.text(name, value),
+            FormPart::File {
+                name,
+                filename,
+                mime,
+                bytes,
+            } => {
+                let part = reqwest::multipart::Part::bytes(bytes)
+                    .file_name(filename)
+                    .mime_str(&mime)
+                    .map_err(Error::InvalidFormPart)?;
+                form.part(name, part)
+            }
+        };
+    }
+    Ok(form)
+}
+
+fn dispatch(
+    client: &reqwest::Client,
+    mut prepared: Prepared,
+    options: &Options,
+) -> Result<reqwest::RequestBuilder, Error> {
+    let headers = options.request_headers(&mut prepared)?;
+    let builder = client
+        .request(prepared.method, prepared.url.as_str())
+        .query(&prepared.query)
+        .headers(headers);
+    let builder = match prepared.body {
+        Some(Body::Bytes(body)) => builder.body(body),
+        Some(Body::Multipart(parts)) => builder.multipart(multipart_form(parts)?),
+        Some(Body::Stream(body)) => match body.take() {
+            Some(Source::Stream(stream)) => builder.body(reqwest::Body::wrap_stream(stream)),
+            Some(Source::Reader(_)) => return Err(Error::UnsupportedBody(
+                "blocking readers can only be sent by ReqwestJsonService",
+            )),
+            None => return Err(Error::UnsupportedBody("streamed bodies can only be sent once")),
+        },
+        None => builder,
+    };
+    let builder = match prepared.timeout {
+        Some(timeout) => builder.timeout(timeout),
+        None => builder,
+    };
+    Ok(match &options.auth {
+        Some(Auth::Bearer(token)) => builder.bearer_auth(token),
+        Some(Auth::Basic { username, password }) => builder.basic_auth(username, password.as_ref()),
+        None => builder,
+    })
+}
+
+async fn exec_request(svc: &AsyncReqwestJsonService, req: Request) -> Result<reqwest::Response, Error> {
+    exec_prepared(svc, prepare_request(&svc.url, &svc.options, req)).await
+}
+
+async fn exec_prepared(
+    svc: &AsyncReqwestJsonService,
+    prepared: Result<Prepared, Error>,
+) -> Result<reqwest::Response, Error> {
+    // Held until the response headers, or an error, are received, the semaphore is never closed
+    let _permit = match &svc.limiter {
+        Some(limiter) => limiter.acquire().await.ok(),
+        None => None,
+    };
+    let started = Instant::now();
+    let result = match prepared {
+        Ok(prepared) => send(svc, prepared).await,
+        Err(err) => Err(err),
+    };
+    if let Some(hook) = &svc.options.on_response {
+        hook(result.as_ref().map(|resp| resp.status().as_u16()), started.elapsed());
+    }
+    result
+}
+
+async fn send(svc: &AsyncReqwestJsonService, prepared: Prepared) -> Result<reqwest::Response, Error> {
+    let url = display_url(prepared.url.as_str());
+    let mut attempt = 0;
+    loop {
+        let result = match dispatch(&svc.client, prepared.clone(), &svc.options) {
+            Ok(builder) => builder
+                .send()
+                .await
+                .map_err(|reqwest_error| request_failed(&url, reqwest_error)),
+            Err(err) => Err(err),
+        };
+        let status = result.as_ref().map(reqwest::Response::status);
+        if !prepared.replayable() || !svc.options.retry.should_retry(attempt, status) {
+            return result;
+        }
+        let requested = result
+            .as_ref()
+            .ok()
+            .and_then(|resp| retry_after(resp.status(), resp.headers()));
+        tokio::time::sleep(svc.options.retry.pause(attempt, requested)).await;
+        attempt += 1;
+    }
+}
+
+async fn extract_text(mut response: reqwest::Response, limit: Option<usize>) -> Result<String, Error> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return response.text().await.map_err(Error::ReadBodyFailed),
+    };
+    if response.content_length().map_or(false, |length| length > limit as u64) {
+        return Err(Error::ResponseTooLarge { limit });
+    }
+    let mut body = CappedBody::new(limit);
+    while let Some(chunk) = response.chunk().await.map_err(Error::ReadBodyFailed)? {
+        body.extend(&chunk)?;
+    }
+    Ok(body.into_text())
+}
+
+/// Body of a failed response as text, cut off at the limit rather than failing so a large error
+/// page still reports the status it was sent with
+async fn extract_payload(mut response: reqwest::Response, limit: Option<usize>) -> Result<String, Error> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return response.text().await.map_err(Error::ReadBodyFailed),
+    };
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(Error::ReadBodyFailed)? {
+        let read = chunk.len().min(limit - body.len());
+        body.extend_from_slice(&chunk[..read]);
+        if body.len() == limit {
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+async fn extract_bytes(mut response: reqwest::Response, limit: Option<usize>) -> Result<Vec<u8>, Error> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return response.bytes().await.map(|bytes| bytes.to_vec()).map_err(Error::ReadBodyFailed),
+    };
+    if response.content_length().map_or(false, |length| length > limit as u64) {
+        return Err(Error::ResponseTooLarge { limit });
+    }
+    let mut body = CappedBody::new(limit);
+    while let Some(chunk) = response.chunk().await.map_err(Error::ReadBodyFailed)? {
+        body.extend(&chunk)?;
+    }
+    Ok(body.bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use futures_util::StreamExt;
+    use mockito::mock;
+
+    use super::{AsyncReqwestJsonService, Endpoint, Error, Request, ResponseResult, ServiceResult};
+
+    fn init() {
+        crate::testing::init();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct TempRequest {}
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct TempResponse {
+        foo: u16,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct TempError {
+        reason: String,
+    }
+
+    impl From<TempRequest> for Request {
+        fn from(_src: TempRequest) -> Request {
+            Request::Get {
+                path: "".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for TempRequest {
+        type TResponse = TempResponse;
+        type TError = TempError;
+    }
+
+    #[tokio::test]
+    async fn parse_payload_successfully() {
+        init();
+        let mock = mock("GET", "/async_parse_payload_successfully")
+            .with_status(200)
+            .with_body(r#"{"foo":10}"#)
+            .expect(1)
+            .create();
+
+        let svc =
+            AsyncReqwestJsonService::with_url("http://www.foo.net/async_parse_payload_successfully")
+                .unwrap();
+
+        match svc.exec(TempRequest {}).await {
+            ServiceResult::Ok (result) => assert_eq!(10, result.foo),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn return_error_for_404_with_error_payload() {
+        init();
+        let mock = mock("GET", "/async_return_error_for_404")
+            .with_status(404)
+            .with_body(r#"{"reason":"missing"}"#)
+            .expect(1)
+            .create();
+
+        let svc =
+            AsyncReqwestJsonService::with_url("http://www.foo.net/async_return_error_for_404")
+                .unwrap();
+
+        match svc.exec(TempRequest {}).await {
+            ServiceResult::Ok(result) => panic!("should have detected invalid status but was [{:?}]", result),
+            ServiceResult::Err(service_error, api_error) => {
+                match service_error {
+                    Error::ResultFailed { .. } => {}
+                    _ => panic!("expected ResultFailed related error but was [{:?}]", service_error),
+                }
+                assert_eq!("missing", api_error.reason);
+            }
+            ServiceResult::Fail(service_error, maybe_api_serde) => panic!(
+                "should have had an api error [{:?}] to parse but was [{:?}]",
+                service_error, maybe_api_serde
+            ),
+        }
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn return_error_for_invalid_payload() {
+        init();
+        let mock = mock("GET", "/async_return_error_for_invalid_payload")
+            .with_status(200)
+            .with_body("foo=bar")
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url(
+            "http://www.foo.net/async_return_error_for_invalid_payload",
+        )
+        .unwrap();
+
+        match svc.exec(TempRequest {}).await {
+            ServiceResult::Ok(result) => panic!("should have been valid status with invalid payload and was [{:?}]", result),
+            ServiceResult::Err(_service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}]", api_error),
+            ServiceResult::Fail(service_error, maybe_api_serde) => {
+                match service_error {
+                    Error::InvalidPayload { .. } => {},
+                    _ => panic!("expected InvalidPayload related error but was [{:?}] with error serde [{:?}]", service_error, maybe_api_serde),
+                }
+                assert!(
+                    maybe_api_serde.is_some(),
+                    "api response should serde error: [{:?}]",
+                    maybe_api_serde
+                );
+            }
+        }
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn return_cancelled_when_signalled_in_flight() {
+        init();
+        let _mock = mock("GET", "/async_return_cancelled_when_signalled_in_flight")
+            .with_status(200)
+            .with_body_from_fn(|w| {
+                thread::sleep(Duration::from_millis(500));
+                w.write_all(br#"{"foo":10}"#)
+            })
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url(
+            "http://www.foo.net/async_return_cancelled_when_signalled_in_flight",
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        let cancel = tokio::time::sleep(Duration::from_millis(50));
+        match svc.exec_cancellable(TempRequest {}, cancel).await {
+            ServiceResult::Fail(Error::Cancelled, None) => {}
+            ServiceResult::Ok(result) => panic!("should have been cancelled but was [{:?}]", result),
+            ServiceResult::Err(service_error, api_error) => panic!("should have been cancelled but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail(service_error, maybe_api_serde) => panic!("expected Cancelled but was [{:?}] with [{:?}]", service_error, maybe_api_serde),
+        }
+        assert!(started.elapsed() < Duration::from_millis(500), "should have returned promptly but took [{:?}]", started.elapsed());
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct ItemRequest {
+        id: u16,
+    }
+
+    impl From<ItemRequest> for Request {
+        fn from(src: ItemRequest) -> Request {
+            Request::Get {
+                path: src.id.to_string(),
+            }
+        }
+    }
+
+    impl Endpoint for ItemRequest {
+        type TResponse = TempResponse;
+        type TError = TempError;
+    }
+
+    #[tokio::test]
+    async fn exec_batch_in_request_order() {
+        init();
+        let mocks: Vec<_> = (1..=4)
+            .map(|id| {
+                mock("GET", format!("/async_exec_batch_in_request_order/{}", id).as_str())
+                    .with_status(200)
+                    .with_body(format!(r#"{{"foo":{}}}"#, id))
+                    .expect(1)
+                    .create()
+            })
+            .collect();
+
+        let svc = AsyncReqwestJsonService::with_url(
+            "http://www.foo.net/async_exec_batch_in_request_order/",
+        )
+        .unwrap();
+
+        let reqs = vec![4, 2, 3, 1].into_iter().map(|id| ItemRequest { id }).collect();
+        let foos: Vec<u16> = svc
+            .exec_batch(reqs)
+            .await
+            .into_iter()
+            .map(|result| match result {
+                ServiceResult::Ok (result) => result.foo,
+                ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+                ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+            })
+            .collect();
+        assert_eq!(vec![4, 2, 3, 1], foos);
+        for mock in mocks {
+            mock.assert();
+        }
+    }
+
+    #[tokio::test]
+    async fn parse_payload_as_json_value() {
+        init();
+        let mock = mock("GET", "/async_parse_payload_as_json_value")
+            .with_status(200)
+            .with_body(r#"{"total":1,"items":[{"name":"foo","active":true}]}"#)
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url(
+            "http://www.foo.net/async_parse_payload_as_json_value",
+        )
+        .unwrap();
+
+        match svc.exec_value(TempRequest {}).await {
+            ResponseResult::Ok (response) => {
+                assert_eq!(1, response.body["total"]);
+                assert_eq!(true, response.body["items"][0]["active"]);
+            }
+            ResponseResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ResponseResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn return_binary_body_intact() {
+        init();
+        let payload: &[u8] = &[0x89, 0x50, 0x4e, 0x47, 0x00, 0xff, 0xfe, 0xc3, 0x28];
+        let mock = mock("GET", "/async_return_binary_body_intact")
+            .with_status(200)
+            .with_header("content-type", "application/x-protobuf")
+            .with_body(payload)
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url(
+            "http://www.foo.net/async_return_binary_body_intact",
+        )
+        .unwrap();
+
+        match svc.exec_bytes(TempRequest {}).await {
+            ResponseResult::Ok (response) => {
+                assert_eq!(payload, response.body.as_slice());
+                assert_eq!(payload.len(), response.bytes_read());
+            }
+            ResponseResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ResponseResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn return_unread_reqwest_response() {
+        init();
+        let mock = mock("GET", "/async_return_unread_reqwest_response")
+            .with_status(503)
+            .with_body("maintenance")
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url(
+            "http://www.foo.net/async_return_unread_reqwest_response",
+        )
+        .unwrap();
+
+        match svc.exec_raw_response(TempRequest {}).await {
+            Ok(response) => {
+                assert_eq!(503, response.status().as_u16());
+                assert_eq!("maintenance", response.text().await.unwrap());
+            }
+            Err(error) => panic!("should have returned the response but failed with [{:?}]", error),
+        }
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn stream_body_chunks_as_they_arrive() {
+        init();
+        let _mock = mock("GET", "/async_stream_body_chunks_as_they_arrive")
+            .with_status(200)
+            .with_body_from_fn(|w| {
+                w.write_all(b"{\"foo\":1}\n")?;
+                w.flush()?;
+                thread::sleep(Duration::from_millis(300));
+                w.write_all(b"{\"foo\":2}\n")
+            })
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url(
+            "http://www.foo.net/async_stream_body_chunks_as_they_arrive",
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        let mut response = svc.exec_stream(TempRequest {}).await.unwrap();
+        assert_eq!(200, response.status);
+
+        let first = response.body.next().await.unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_millis(300), "first chunk should arrive before the rest but took [{:?}]", started.elapsed());
+        let mut received = first.to_vec();
+        while let Some(chunk) = response.body.next().await {
+            received.extend_from_slice(&chunk.unwrap());
+        }
+        assert!(first.starts_with(b"{\"foo\":1}"), "unexpected first chunk [{:?}]", first);
+        assert_eq!(b"{\"foo\":1}\n{\"foo\":2}\n".to_vec(), received);
+    }
+
+    #[tokio::test]
+    async fn keep_status_of_large_error_while_streaming() {
+        init();
+        let mock = mock("GET", "/async_keep_status_of_large_error_while_streaming")
+            .with_status(503)
+            .with_body("upstream is down for maintenance")
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::builder()
+            .url("http://www.foo.net/async_keep_status_of_large_error_while_streaming")
+            .max_response_bytes(8)
+            .build_async()
+            .unwrap();
+
+        match svc.exec_stream(TempRequest {}).await {
+            Ok(response) => panic!("should have failed with the status but was [{}]", response.status),
+            Err(Error::ResultFailed { status, payload, .. }) => {
+                assert_eq!(503, status);
+                assert_eq!("upstream", payload);
+            }
+            Err(error) => panic!("expected ResultFailed but was [{:?}]", error),
+        }
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn parse_ndjson_a_line_at_a_time() {
+        init();
+        let mock = mock("GET", "/async_parse_ndjson_a_line_at_a_time")
+            .with_status(200)
+            .with_header("content-type", "application/x-ndjson")
+            .with_body_from_fn(|w| {
+                w.write_all(b"{\"foo\":1}\n{\"fo")?;
+                w.flush()?;
+                w.write_all(b"o\":2}\r\n\n{\"bar\":3}\n{\"foo\":4}")
+            })
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url(
+            "http://www.foo.net/async_parse_ndjson_a_line_at_a_time",
+        )
+        .unwrap();
+
+        let response = svc.exec_ndjson(TempRequest {}).await.unwrap();
+        let items: Vec<_> = response.body.collect().await;
+
+        assert_eq!(4, items.len(), "unexpected items [{:?}]", items);
+        assert_eq!(1, items[0].as_ref().unwrap().foo);
+        assert_eq!(2, items[1].as_ref().unwrap().foo);
+        match &items[2] {
+            Err(Error::InvalidPayload { payload, .. }) => assert_eq!(r#"{"bar":3}"#, payload.as_str()),
+            item => panic!("expected InvalidPayload for the malformed line but was [{:?}]", item),
+        }
+        assert_eq!(4, items[3].as_ref().unwrap().foo);
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn send_with_cap_beyond_semaphore_permits() {
+        init();
+        let mock = mock("GET", "/async_send_with_cap_beyond_semaphore_permits")
+            .with_status(200)
+            .with_body(r#"{"foo":10}"#)
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::builder()
+            .url("http://www.foo.net/async_send_with_cap_beyond_semaphore_permits")
+            .max_concurrent(usize::MAX)
+            .build_async()
+            .unwrap();
+
+        match svc.exec(TempRequest {}).await {
+            ServiceResult::Ok (result) => assert_eq!(10, result.foo),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+}
@@ -0,0 +1,2003 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use gateway::parse_url;
+
+use crate::{parse_header, Auth, CorrelationId, Error, Interceptor, Options, Request, Retry, Signer};
+#[cfg(feature = "async")]
+use crate::AsyncReqwestJsonService;
+#[cfg(feature = "blocking")]
+use crate::ReqwestJsonService;
+
+/// Http version the service speaks, see `ReqwestJsonServiceBuilder::http_version`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HttpVersion {
+    /// Only ever speak HTTP/1.1, e.g. behind load balancers which don't handle HTTP/2
+    Http1,
+    /// Speak HTTP/2 from the start of every connection without negotiating it
+    Http2,
+    /// Negotiate the version with the api, which is the default
+    Auto,
+}
+
+/// Client level configuration resolved from the builder
+struct ClientConfig {
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    default_headers: reqwest::header::HeaderMap,
+    redirect: Option<reqwest::redirect::Policy>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Option<Duration>>,
+    tcp_nodelay: Option<bool>,
+    tcp_keepalive: Option<Option<Duration>>,
+    proxy: Option<reqwest::Proxy>,
+    decompress: Option<bool>,
+    identity: Option<reqwest::Identity>,
+    root_certificates: Vec<reqwest::Certificate>,
+    accept_invalid_certs: bool,
+    cookie_store: bool,
+    http1_only: bool,
+    http2_prior_knowledge: bool,
+}
+
+/// Applies the client configuration to either the blocking or the async reqwest client builder,
+/// which expose the same configuration methods without sharing a trait
+macro_rules! configure_client {
+    ($builder:expr, $config:expr) => {{
+        let config: ClientConfig = $config;
+        let mut builder = $builder.default_headers(config.default_headers);
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(redirect) = config.redirect {
+            builder = builder.redirect(redirect);
+        }
+        if let Some(max) = config.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = config.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(enabled) = config.tcp_nodelay {
+            builder = builder.tcp_nodelay(enabled);
+        }
+        if let Some(interval) = config.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(proxy) = config.proxy {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(enabled) = config.decompress {
+            builder = builder.gzip(enabled).brotli(enabled);
+        }
+        if let Some(identity) = config.identity {
+            builder = builder.identity(identity);
+        }
+        for certificate in config.root_certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+        if config.accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if config.cookie_store {
+            builder = builder.cookie_store(true);
+        }
+        if config.http1_only {
+            builder = builder.http1_only();
+        }
+        if config.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        builder
+    }};
+}
+
+/// Applies any reqwest setting to the blocking client builder, see `configure_client`
+#[cfg(feature = "blocking")]
+type ConfigureClient =
+    Box<dyn FnOnce(reqwest::blocking::ClientBuilder) -> reqwest::blocking::ClientBuilder>;
+
+/// Applies any reqwest setting to the async client builder, see `configure_async_client`
+#[cfg(feature = "async")]
+type ConfigureAsyncClient = Box<dyn FnOnce(reqwest::ClientBuilder) -> reqwest::ClientBuilder>;
+
+/// Incrementally configure a service, deferring validation until it is built
+pub struct ReqwestJsonServiceBuilder {
+    url: Option<String>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    default_headers: Result<reqwest::header::HeaderMap, Error>,
+    redirect: Option<reqwest::redirect::Policy>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Option<Duration>>,
+    tcp_nodelay: Option<bool>,
+    tcp_keepalive: Option<Option<Duration>>,
+    proxy: Option<Result<reqwest::Proxy, Error>>,
+    proxy_auth: Option<(String, String)>,
+    decompress: Option<bool>,
+    identity: Option<Result<reqwest::Identity, Error>>,
+    root_certificates: Result<Vec<reqwest::Certificate>, Error>,
+    accept_invalid_certs: bool,
+    cookie_store: bool,
+    http1_only: bool,
+    http2_prior_knowledge: bool,
+    http_version: Option<HttpVersion>,
+    #[cfg(feature = "blocking")]
+    configure_client: Option<ConfigureClient>,
+    #[cfg(feature = "async")]
+    configure_async_client: Option<ConfigureAsyncClient>,
+    correlation: Option<(Result<reqwest::header::HeaderName, Error>, CorrelationId)>,
+    options: Options,
+}
+
+impl Default for ReqwestJsonServiceBuilder {
+    fn default() -> Self {
+        ReqwestJsonServiceBuilder::new()
+    }
+}
+
+impl ReqwestJsonServiceBuilder {
+    pub fn new() -> Self {
+        ReqwestJsonServiceBuilder {
+            url: None,
+            timeout: None,
+            connect_timeout: None,
+            default_headers: Ok(reqwest::header::HeaderMap::new()),
+            redirect: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            tcp_nodelay: None,
+            tcp_keepalive: None,
+            proxy: None,
+            proxy_auth: None,
+            decompress: None,
+            identity: None,
+            root_certificates: Ok(Vec::new()),
+            accept_invalid_certs: false,
+            cookie_store: false,
+            http1_only: false,
+            http2_prior_knowledge: false,
+            http_version: None,
+            #[cfg(feature = "blocking")]
+            configure_client: None,
+            #[cfg(feature = "async")]
+            configure_async_client: None,
+            correlation: None,
+            options: Options::default(),
+        }
+    }
+
+    /// Base url which request paths are resolved against
+    pub fn url(mut self, url_str: &str) -> Self {
+        self.url = Some(url_str.to_owned());
+        self
+    }
+
+    /// Fail any request which takes longer than `timeout` to complete, from connecting through
+    /// reading the whole response
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail any request which takes longer than `timeout` just to connect, e.g. to give up on
+    /// unreachable hosts quickly while still allowing slow responses, surfacing the timeout
+    /// through `Error::TimedOut`
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Send the header with every request made by the service unless the request provides its
+    /// own value for the header, an invalid header fails the subsequent `build`
+    pub fn default_header(mut self, name: &str, value: &str) -> Self {
+        self.default_headers = self.default_headers.and_then(|mut headers| {
+            let (name, value) = parse_header(name, value)?;
+            headers.append(name, value);
+            Ok(headers)
+        });
+        self
+    }
+
+    /// Send the headers with every request made by the service unless the request provides its
+    /// own value for a header, replacing any defaults previously configured for the same names
+    pub fn default_headers(mut self, headers: reqwest::header::HeaderMap) -> Self {
+        self.default_headers = self.default_headers.map(|mut existing| {
+            existing.extend(headers);
+            existing
+        });
+        self
+    }
+
+    /// Identify the service with a `User-Agent` header on every request, replacing any previously
+    /// configured, an invalid value fails the subsequent `build`
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.default_headers = self.default_headers.and_then(|mut headers| {
+            let (name, value) = parse_header("User-Agent", user_agent)?;
+            headers.insert(name, value);
+            Ok(headers)
+        });
+        self
+    }
+
+    /// Send no `User-Agent` header unless a request provides its own, removing any previously
+    /// configured, which is the default as reqwest doesn't add one itself
+    pub fn no_user_agent(mut self) -> Self {
+        self.default_headers = self.default_headers.map(|mut headers| {
+            headers.remove(reqwest::header::USER_AGENT);
+            headers
+        });
+        self
+    }
+
+    /// Media type sent in the `Accept` header of requests which don't provide their own, e.g.
+    /// `application/vnd.github+json`, replacing any previously configured, defaults to
+    /// `application/json`
+    pub fn accept(mut self, media_type: &str) -> Self {
+        self.default_headers = self.default_headers.and_then(|mut headers| {
+            let (name, value) = parse_header("Accept", media_type)?;
+            headers.insert(name, value);
+            Ok(headers)
+        });
+        self
+    }
+
+    /// Control whether redirects are followed, e.g. `Policy::none()` to surface a 3xx as
+    /// `Error::ResultFailed` or `Policy::limited(n)` to cap them, defaults to following up to 10
+    pub fn redirect(mut self, policy: reqwest::redirect::Policy) -> Self {
+        self.redirect = Some(policy);
+        self
+    }
+
+    /// Cap the idle connections kept open to each host, defaults to reqwest's unbounded pool
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Close pooled connections left idle for longer than `timeout`, or never when `None`,
+    /// defaults to reqwest's 90 seconds
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set `TCP_NODELAY` on connections so small requests are sent without waiting to coalesce
+    /// with further writes, defaults to reqwest's own setting
+    pub fn tcp_nodelay(mut self, enabled: bool) -> Self {
+        self.tcp_nodelay = Some(enabled);
+        self
+    }
+
+    /// Probe idle connections with tcp keepalives at the interval to detect dead peers, or never
+    /// when `None`, defaults to reqwest's own setting
+    pub fn tcp_keepalive(mut self, interval: Option<Duration>) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Route all http and https traffic through the proxy at `url_str`, a malformed url fails
+    /// the subsequent `build` with `Error::InvalidProxy`
+    pub fn proxy(mut self, url_str: &str) -> Self {
+        self.proxy = Some(reqwest::Proxy::all(url_str).map_err(Error::InvalidProxy));
+        self
+    }
+
+    /// Authenticate with the configured proxy using http basic auth credentials, without a
+    /// `proxy` the subsequent `build` fails with `Error::InvalidOption`
+    pub fn proxy_basic_auth(mut self, username: &str, password: &str) -> Self {
+        self.proxy_auth = Some((username.to_owned(), password.to_owned()));
+        self
+    }
+
+    /// Present the client certificate from a DER encoded PKCS#12 archive for mutual tls, invalid
+    /// material fails the subsequent `build` with `Error::InvalidIdentity`
+    pub fn identity_pkcs12(mut self, der: &[u8], password: &str) -> Self {
+        self.identity = Some(
+            reqwest::Identity::from_pkcs12_der(der, password).map_err(Error::InvalidIdentity),
+        );
+        self
+    }
+
+    /// Present the PEM encoded client certificate chain and PKCS#8 private key for mutual tls,
+    /// invalid material fails the subsequent `build` with `Error::InvalidIdentity`
+    pub fn identity_pem(mut self, certificate: &[u8], key: &[u8]) -> Self {
+        self.identity = Some(
+            reqwest::Identity::from_pkcs8_pem(certificate, key).map_err(Error::InvalidIdentity),
+        );
+        self
+    }
+
+    /// Trust servers whose certificates are signed by the PEM encoded root, e.g. a private CA, in
+    /// addition to the system's roots, invalid material fails the subsequent `build` with
+    /// `Error::InvalidCertificate`
+    pub fn add_root_certificate(mut self, pem: &[u8]) -> Self {
+        self.root_certificates = self.root_certificates.and_then(|mut certificates| {
+            let certificate = reqwest::Certificate::from_pem(pem).map_err(Error::InvalidCertificate)?;
+            certificates.push(certificate);
+            Ok(certificates)
+        });
+        self
+    }
+
+    /// DANGER: skip verification of server certificates, accepting expired, self-signed or
+    /// otherwise untrusted certificates and hostname mismatches, only ever for local development
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Keep cookies set by responses and send them back on subsequent requests made through the
+    /// same service, e.g. a session cookie set on login
+    pub fn cookie_store(mut self, enabled: bool) -> Self {
+        self.cookie_store = enabled;
+        self
+    }
+
+    /// Only ever speak HTTP/1.1, which can't be combined with `http2_prior_knowledge`
+    pub fn http1_only(mut self) -> Self {
+        self.http1_only = true;
+        self
+    }
+
+    /// Speak HTTP/2 from the start of every connection without negotiating it, for apis known to
+    /// support it, which can't be combined with `http1_only`
+    pub fn http2_prior_knowledge(mut self) -> Self {
+        self.http2_prior_knowledge = true;
+        self
+    }
+
+    /// Pin the http version the service speaks, replacing any version previously provided, which
+    /// can't be combined with a conflicting `http1_only` or `http2_prior_knowledge`, nor with
+    /// either of them for `HttpVersion::Auto`
+    pub fn http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = Some(version);
+        self
+    }
+
+    /// Advertise and transparently decode `gzip` and `br` encoded responses before they are
+    /// parsed, defaults to enabled
+    pub fn decompress(mut self, enabled: bool) -> Self {
+        self.decompress = Some(enabled);
+        self
+    }
+
+    /// Authorize every request with an `Authorization: Bearer <token>` header
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.options.auth = Some(Auth::Bearer(token.to_owned()));
+        self
+    }
+
+    /// Authorize every request with http basic auth credentials
+    pub fn basic_auth(mut self, username: &str, password: Option<&str>) -> Self {
+        self.options.auth = Some(Auth::Basic {
+            username: username.to_owned(),
+            password: password.map(str::to_owned),
+        });
+        self
+    }
+
+    /// Re-attempt requests which failed for transient reasons, see `ReqwestJsonService::with_retries`
+    pub fn retries(mut self, max: u32, base_delay: Duration) -> Self {
+        self.options.retry = Retry {
+            max,
+            base_delay,
+            ..self.options.retry
+        };
+        self
+    }
+
+    /// Cap how long a retry waits when a 429 or 503 response requests a `Retry-After`, defaults
+    /// to 60 seconds
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        self.options.retry.max_retry_after = Some(max);
+        self
+    }
+
+    /// Don't attempt to parse the bodies of failed responses as the endpoint's error type, so
+    /// failures are always reported as `ServiceResult::Fail(error, None)`
+    pub fn skip_error_payloads(mut self) -> Self {
+        self.options.skip_error_payloads = true;
+        self
+    }
+
+    /// Coalesce identical `GET` requests made while one is already in flight, including their
+    /// headers, so only the first is sent and the others share its response, e.g. to spare the
+    /// backing api bursts of requests for the same hot resource, where callers waiting on a
+    /// request which fails send their own as failures can't be shared
+    pub fn single_flight(mut self) -> Self {
+        self.options.single_flight = Some(Arc::default());
+        self
+    }
+
+    /// Allow at most `max` requests to be in flight at once across all callers of the service,
+    /// additional requests wait for one to complete before being sent, e.g. to smooth bursts
+    /// which would otherwise overwhelm the backing api, a `max` of 0 fails the subsequent `build`
+    /// with `Error::InvalidOption` as no request could ever be sent while `build_async` caps
+    /// `max` at `tokio::sync::Semaphore::MAX_PERMITS`
+    pub fn max_concurrent(mut self, max: usize) -> Self {
+        self.options.max_concurrent = Some(max);
+        self
+    }
+
+    /// Fail successful responses with `Error::UnexpectedContentType` instead of parsing them
+    /// unless their `Content-Type` is json, e.g. `application/json` or `application/hal+json`,
+    /// where empty bodies are still parsed as `null`
+    pub fn strict_content_type(mut self) -> Self {
+        self.options.strict_content_type = true;
+        self
+    }
+
+    /// Treat successful responses carrying a non-empty GraphQL style `errors` array as failures,
+    /// reported as `Error::GraphQlErrors` with the whole payload parsed as the endpoint's error
+    /// type, where `envelope("data")` parses the remaining responses from their `data`
+    pub fn graphql(mut self) -> Self {
+        self.options.graphql = true;
+        self
+    }
+
+    /// Parse responses from the value at the json pointer, e.g. `/data` for apis which wrap
+    /// every payload in an envelope such as `{"data": {..}, "meta": {..}}`, where a bare field
+    /// name is taken as a pointer to that field, error payloads are parsed as they are
+    pub fn envelope(mut self, pointer: &str) -> Self {
+        self.options.envelope = Some(if pointer.starts_with('/') {
+            pointer.to_owned()
+        } else {
+            format!("/{}", pointer)
+        });
+        self
+    }
+
+    /// Abandon reading response bodies larger than `limit` bytes with `Error::ResponseTooLarge`,
+    /// so a misbehaving api can't exhaust memory, where capped bodies are read as utf-8
+    /// regardless of the charset of the response
+    pub fn max_response_bytes(mut self, limit: usize) -> Self {
+        self.options.max_response_bytes = Some(limit);
+        self
+    }
+
+    /// Remember the body and `ETag` of `GET` responses by url and send the `ETag` back as
+    /// `If-None-Match`, so a `304 Not Modified` is parsed from the remembered body while the
+    /// response keeps its 304 status to signal the cached entry is still valid
+    ///
+    /// Urls differing only in their query are remembered apart, so at most 1024 responses are
+    /// kept, evicting the least recently used to make room for more.
+    ///
+    /// Only requests whose body is parsed as text are made conditional, so `exec_bytes`,
+    /// `exec_raw_response`, `inspect` and `health_check` always receive the full response.
+    pub fn etag_cache(mut self) -> Self {
+        self.options.etags = Some(Arc::default());
+        self
+    }
+
+    /// Transform every request before it is sent and every response before it is processed,
+    /// after any interceptors previously added
+    pub fn interceptor<I>(mut self, interceptor: I) -> Self
+    where
+        I: Interceptor + 'static,
+    {
+        self.options.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Forward the correlation id of the work in progress, e.g. `traceparent` or
+    /// `X-Correlation-ID`, in the header on every request unless the request provides its own,
+    /// where `id` is asked for the id each time a request is sent and `None` sends no header,
+    /// an invalid header name fails the subsequent `build`
+    pub fn correlation_id<F>(mut self, header: &str, id: F) -> Self
+    where
+        F: Fn() -> Option<String> + Send + Sync + 'static,
+    {
+        let header = reqwest::header::HeaderName::from_bytes(header.as_bytes())
+            .map_err(Error::InvalidHeaderName);
+        self.correlation = Some((header, Arc::new(id)));
+        self
+    }
+
+    /// Add the headers computed by the signer to every request right before it is sent, e.g. to
+    /// authenticate with a cloud api, replacing any signer previously provided
+    pub fn signer<S>(mut self, signer: S) -> Self
+    where
+        S: Signer + 'static,
+    {
+        self.options.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Log each request about to be executed at `level` instead of debug, under `LOG_TARGET`
+    pub fn log_level(mut self, level: log::Level) -> Self {
+        self.options.log_level = Some(level);
+        self
+    }
+
+    /// Observe every request before it is sent, e.g. to open a tracing span or log it
+    pub fn on_request<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Request) + Send + Sync + 'static,
+    {
+        self.options.on_request = Some(Arc::new(hook));
+        self
+    }
+
+    /// Observe the final status, or the error, of every request along with the time it took
+    /// including any retries, the body is not yet read or parsed when the hook runs
+    pub fn on_response<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(Result<u16, &Error>, Duration) + Send + Sync + 'static,
+    {
+        self.options.on_response = Some(Arc::new(hook));
+        self
+    }
+
+    /// Parse responses with the status as the endpoint's response type, in addition to any 2xx
+    pub fn accept_status(mut self, status: u16) -> Self {
+        self.options.accepted_statuses.push(status);
+        self
+    }
+
+    /// Describe failures with the status by the message, e.g. `Service temporarily unavailable`
+    /// for a 503, which leads the `Display` of `Error::ResultFailed` in place of the default
+    pub fn status_message(mut self, status: u16, message: &str) -> Self {
+        self.options.status_messages.insert(status, message.to_owned());
+        self
+    }
+
+    /// Apply any reqwest setting which isn't otherwise exposed to the blocking client, after the
+    /// rest of the configuration, replacing any function previously provided
+    #[cfg(feature = "blocking")]
+    pub fn configure_client<F>(mut self, configure: F) -> Self
+    where
+        F: FnOnce(reqwest::blocking::ClientBuilder) -> reqwest::blocking::ClientBuilder + 'static,
+    {
+        self.configure_client = Some(Box::new(configure));
+        self
+    }
+
+    /// Async equivalent of `configure_client`, applied by `build_async`
+    #[cfg(feature = "async")]
+    pub fn configure_async_client<F>(mut self, configure: F) -> Self
+    where
+        F: FnOnce(reqwest::ClientBuilder) -> reqwest::ClientBuilder + 'static,
+    {
+        self.configure_async_client = Some(Box::new(configure));
+        self
+    }
+
+    /// Construct a blocking service from the configuration
+    #[cfg(feature = "blocking")]
+    pub fn build(mut self) -> Result<ReqwestJsonService, Error> {
+        let configure = self.configure_client.take();
+        let (url, config, options) = self.into_parts()?;
+        let mut builder = configure_client!(reqwest::blocking::Client::builder(), config);
+        if let Some(configure) = configure {
+            builder = configure(builder);
+        }
+        let client = builder.build().map_err(Error::ClientBuildFailed)?;
+        Ok(ReqwestJsonService::from_parts(url, client, options))
+    }
+
+    /// Construct an async service from the configuration
+    #[cfg(feature = "async")]
+    pub fn build_async(mut self) -> Result<AsyncReqwestJsonService, Error> {
+        let configure = self.configure_async_client.take();
+        let (url, config, options) = self.into_parts()?;
+        let mut builder = configure_client!(reqwest::Client::builder(), config);
+        if let Some(configure) = configure {
+            builder = configure(builder);
+        }
+        let client = builder.build().map_err(Error::ClientBuildFailed)?;
+        Ok(AsyncReqwestJsonService::from_parts(url, client, options))
+    }
+
+    fn into_parts(mut self) -> Result<(url::Url, ClientConfig, Options), Error> {
+        // A missing url is reported the same way as an empty one
+        let url = parse_url(self.url.as_ref().map_or("", String::as_str)).map_err(Error::InvalidUrl)?;
+        let mut default_headers = self.default_headers?;
+        if let Some((header, id)) = self.correlation {
+            self.options.correlation = Some((header?, id));
+        }
+        if self.options.max_concurrent == Some(0) {
+            return Err(Error::InvalidOption("max_concurrent(0)"));
+        }
+        if self.proxy_auth.is_some() && self.proxy.is_none() {
+            return Err(Error::InvalidOption("proxy_basic_auth without proxy"));
+        }
+        if self.http1_only && self.http2_prior_knowledge {
+            return Err(Error::ConflictingOptions("http1_only and http2_prior_knowledge"));
+        }
+        match self.http_version {
+            Some(HttpVersion::Http1) if self.http2_prior_knowledge => {
+                return Err(Error::ConflictingOptions("http_version(Http1) and http2_prior_knowledge"))
+            }
+            Some(HttpVersion::Http2) if self.http1_only => {
+                return Err(Error::ConflictingOptions("http_version(Http2) and http1_only"))
+            }
+            Some(HttpVersion::Auto) if self.http1_only || self.http2_prior_knowledge => {
+                return Err(Error::ConflictingOptions(
+                    "http_version(Auto) and http1_only or http2_prior_knowledge",
+                ))
+            }
+            Some(HttpVersion::Http1) => self.http1_only = true,
+            Some(HttpVersion::Http2) => self.http2_prior_knowledge = true,
+            Some(HttpVersion::Auto) | None => {}
+        }
+        // The service always negotiates the media type per request so a configured default takes
+        // the place of `application/json`
+        if let Some(accept) = default_headers.remove(reqwest::header::ACCEPT) {
+            self.options.accept = Some(accept);
+        }
+        let config = ClientConfig {
+            timeout: self.timeout,
+            connect_timeout: self.connect_timeout,
+            default_headers,
+            redirect: self.redirect,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            pool_idle_timeout: self.pool_idle_timeout,
+            tcp_nodelay: self.tcp_nodelay,
+            tcp_keepalive: self.tcp_keepalive,
+            proxy: match (self.proxy.transpose()?, self.proxy_auth) {
+                (Some(proxy), Some((username, password))) => {
+                    Some(proxy.basic_auth(&username, &password))
+                }
+                (proxy, _) => proxy,
+            },
+            decompress: self.decompress,
+            identity: self.identity.transpose()?,
+            root_certificates: self.root_certificates?,
+            accept_invalid_certs: self.accept_invalid_certs,
+            cookie_store: self.cookie_store,
+            http1_only: self.http1_only,
+            http2_prior_knowledge: self.http2_prior_knowledge,
+        };
+        Ok((url, config, self.options))
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use gateway::{Endpoint, Service, ServiceResult};
+    use mockito::mock;
+    use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+    use reqwest::redirect::Policy;
+
+    use super::{Error, HttpVersion, Request, ReqwestJsonService, ReqwestJsonServiceBuilder};
+    use crate::{ResponseResult, Signer};
+
+    fn init() {
+        crate::testing::init();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Unit {}
+
+    impl From<Unit> for Request {
+        fn from(_: Unit) -> Request {
+            Request::Get {
+                path: "".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for Unit {
+        type TResponse = Unit;
+        type TError = ();
+    }
+
+    #[test]
+    fn fail_build_without_url() {
+        init();
+        match ReqwestJsonServiceBuilder::new().build() {
+            Ok(svc) => panic!("should have failed without url but was [{:?}]", svc),
+            Err(Error::InvalidUrl(gateway::Error::UrlParseFailed(_))) => {}
+            Err(error) => panic!("expected InvalidUrl but was [{:?}]", error),
+        }
+    }
+
+    #[test]
+    fn fail_build_with_invalid_default_header() {
+        init();
+        let result = ReqwestJsonService::builder()
+            .url("http://www.foo.net/fail_build_with_invalid_default_header")
+            .default_header("X Api Version", "2")
+            .build();
+
+        match result {
+            Ok(svc) => panic!("should have failed invalid header but was [{:?}]", svc),
+            Err(Error::InvalidHeaderName(_)) => {}
+            Err(error) => panic!("expected InvalidHeaderName but was [{:?}]", error),
+        }
+    }
+
+    #[test]
+    fn send_configuration_from_builder() {
+        init();
+        let mock = mock("GET", "/send_configuration_from_builder")
+            .match_header("x-api-version", "2")
+            .match_header("authorization", "Bearer secret-token")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/send_configuration_from_builder")
+            .default_header("X-Api-Version", "2")
+            .bearer_auth("secret-token")
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct VersionedUnit {}
+
+    impl From<VersionedUnit> for Request {
+        fn from(_: VersionedUnit) -> Request {
+            Request::Get {
+                path: "".to_owned(),
+            }
+            .with_header("X-Api-Version", "3")
+        }
+    }
+
+    impl Endpoint for VersionedUnit {
+        type TResponse = Unit;
+        type TError = ();
+    }
+
+    #[test]
+    fn override_default_headers_per_request() {
+        init();
+        let mock = mock("GET", "/override_default_headers_per_request")
+            .match_header("user-agent", "gateway-test")
+            .match_header("x-api-version", "3")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("gateway-test"));
+        headers.insert("x-api-version", HeaderValue::from_static("2"));
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/override_default_headers_per_request")
+            .default_headers(headers)
+            .build()
+            .unwrap();
+
+        match svc.exec(VersionedUnit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn send_configured_accept_header() {
+        init();
+        let mock = mock("GET", "/send_configured_accept_header")
+            .match_header("accept", "application/vnd.github+json")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/send_configured_accept_header")
+            .accept("application/vnd.github+json")
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn send_last_configured_accept_header() {
+        init();
+        let mock = mock("GET", "/send_last_configured_accept_header")
+            .match_header("accept", "application/hal+json")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/send_last_configured_accept_header")
+            .accept("application/vnd.github+json")
+            .accept("application/hal+json")
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn send_configured_user_agent() {
+        init();
+        let mock = mock("GET", "/send_configured_user_agent")
+            .match_header("user-agent", "gateway-test/1.0")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/send_configured_user_agent")
+            .user_agent("gateway-test/0.9")
+            .user_agent("gateway-test/1.0")
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn apply_custom_client_configuration() {
+        init();
+        let mock = mock("GET", "/apply_custom_client_configuration")
+            .match_header("user-agent", "gateway-custom/1.0")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/apply_custom_client_configuration")
+            .configure_client(|builder| builder.user_agent("gateway-custom/1.0"))
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn send_no_user_agent_when_disabled() {
+        init();
+        let mock = mock("GET", "/send_no_user_agent_when_disabled")
+            .match_header("user-agent", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/send_no_user_agent_when_disabled")
+            .user_agent("gateway-test/1.0")
+            .no_user_agent()
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn follow_redirects_by_default() {
+        init();
+        let redirect = mock("GET", "/follow_redirects_by_default")
+            .with_status(302)
+            .with_header("location", "/follow_redirects_by_default_target")
+            .expect(1)
+            .create();
+        let target = mock("GET", "/follow_redirects_by_default_target")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/follow_redirects_by_default")
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        redirect.assert();
+        target.assert();
+    }
+
+    #[test]
+    fn return_fail_for_redirect_when_disabled() {
+        init();
+        let redirect = mock("GET", "/return_fail_for_redirect_when_disabled")
+            .with_status(302)
+            .with_header("location", "/return_fail_for_redirect_when_disabled_target")
+            .expect(1)
+            .create();
+        let target = mock("GET", "/return_fail_for_redirect_when_disabled_target")
+            .with_status(200)
+            .with_body("{}")
+            .expect(0)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/return_fail_for_redirect_when_disabled")
+            .redirect(Policy::none())
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok(result) => panic!("should not have followed the redirect but was [{:?}]", result),
+            ServiceResult::Err(service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}] and [{:?}]", service_error, api_error),
+            ServiceResult::Fail(service_error, _maybe_api_serde) => match service_error {
+                Error::ResultFailed { status, .. } => assert_eq!(302, status),
+                _ => panic!("expected ResultFailed related error but was [{:?}]", service_error),
+            },
+        }
+        redirect.assert();
+        target.assert();
+    }
+
+    #[test]
+    fn invoke_hooks_around_request() {
+        init();
+        let mock = mock("GET", "/invoke_hooks_around_request")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (requests, responses) = (events.clone(), events.clone());
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/invoke_hooks_around_request")
+            .on_request(move |req| requests.lock().unwrap().push(format!("request {:?}", req)))
+            .on_response(move |result, _elapsed| {
+                responses.lock().unwrap().push(format!("response {:?}", result.ok()))
+            })
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        assert_eq!(
+            vec![r#"request Get { path: "" }"#.to_owned(), "response Some(200)".to_owned()],
+            *events.lock().unwrap()
+        );
+        mock.assert();
+    }
+
+    #[test]
+    fn fail_fast_connecting_to_unroutable_host() {
+        init();
+        // Test urls are all routed to the mock server, an unroutable proxy stands in for a dead host
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/fail_fast_connecting_to_unroutable_host")
+            .proxy("http://10.255.255.1:3128")
+            .connect_timeout(Duration::from_millis(200))
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
+
+        let started = Instant::now();
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (result) => panic!("should have failed connecting but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should have failed connecting but was [{:?}] and [{:?}]", service_error, api_error),
+            // Hosts without a route to the address refuse it outright rather than timing out
+            ServiceResult::Fail (Error::TimedOut { .. }, None) | ServiceResult::Fail (Error::ConnectFailed { .. }, None) => {},
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("expected TimedOut but was [{:?}] and [{:?}]", service_error, maybe_api_serde),
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed < Duration::from_secs(5), "expected to give up connecting quickly but took [{:?}]", elapsed);
+    }
+
+    #[test]
+    fn cap_requests_in_flight() {
+        init();
+        let mock = mock("GET", "/cap_requests_in_flight")
+            .with_status(200)
+            .with_body("{}")
+            .expect(6)
+            .create();
+
+        // (in flight, most seen in flight at once)
+        let counts = Arc::new(Mutex::new((0, 0)));
+        let (started, finished) = (counts.clone(), counts.clone());
+        let svc = Arc::new(
+            ReqwestJsonService::builder()
+                .url("http://www.foo.net/cap_requests_in_flight")
+                .max_concurrent(2)
+                .on_request(move |_| {
+                    let mut counts = started.lock().unwrap();
+                    counts.0 += 1;
+                    counts.1 = counts.1.max(counts.0);
+                })
+                .on_response(move |_, _| finished.lock().unwrap().0 -= 1)
+                .build()
+                .unwrap(),
+        );
+
+        let handles: Vec<_> = (0..6)
+            .map(|_| {
+                let svc = svc.clone();
+                thread::spawn(move || match svc.exec(Unit {}) {
+                    ServiceResult::Ok (_) => {},
+                    ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+                    ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let (in_flight, most) = *counts.lock().unwrap();
+        assert_eq!(0, in_flight);
+        assert!(most <= 2, "expected at most 2 requests in flight but saw [{}]", most);
+        mock.assert();
+    }
+
+    #[test]
+    fn send_with_pool_configuration() {
+        init();
+        let mock = mock("GET", "/send_with_pool_configuration")
+            .with_status(200)
+            .with_body("{}")
+            .expect(3)
+            .create();
+        let builder = || {
+            ReqwestJsonService::builder()
+                .url("http://www.foo.net/send_with_pool_configuration")
+                .pool_max_idle_per_host(1)
+                .pool_idle_timeout(Some(Duration::from_secs(30)))
+        };
+
+        #[cfg(feature = "async")]
+        if let Err(error) = builder().build_async() {
+            panic!("should have built async service but failed with [{:?}]", error);
+        }
+        let svc = builder().build().unwrap();
+        // Consecutive requests reuse the single idle connection kept for the host
+        for _ in 0..3 {
+            match svc.exec(Unit {}) {
+                ServiceResult::Ok (_) => {},
+                ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+                ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+            }
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn send_with_tcp_configuration() {
+        init();
+        let mock = mock("GET", "/send_with_tcp_configuration")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+        let builder = || {
+            ReqwestJsonService::builder()
+                .url("http://www.foo.net/send_with_tcp_configuration")
+                .tcp_nodelay(true)
+                .tcp_keepalive(Some(Duration::from_secs(60)))
+        };
+
+        #[cfg(feature = "async")]
+        if let Err(error) = builder().build_async() {
+            panic!("should have built async service but failed with [{:?}]", error);
+        }
+        match builder().build().unwrap().exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn send_with_pinned_protocol() {
+        init();
+        let mock = mock("GET", "/send_with_pinned_protocol")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+        let builder = || {
+            ReqwestJsonService::builder()
+                .url("http://www.foo.net/send_with_pinned_protocol")
+                .timeout(Duration::from_secs(5))
+        };
+
+        match builder().http1_only().build().unwrap().exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        // The mock server only speaks http1 so skipping negotiation leaves nothing to talk to
+        match builder().http2_prior_knowledge().build().unwrap().exec(Unit {}) {
+            ServiceResult::Ok (result) => panic!("should have failed speaking http2 to an http1 server but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should have failed speaking http2 to an http1 server but was [{:?}] and [{:?}]", service_error, api_error),
+            ServiceResult::Fail (_, None) => {},
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have had an api error to parse for [{:?}] but was [{:?}]", service_error, maybe_api_serde),
+        }
+        #[cfg(feature = "async")]
+        if let Err(error) = builder().http2_prior_knowledge().build_async() {
+            panic!("should have built http2 service but failed with [{:?}]", error);
+        }
+        match builder().http1_only().http2_prior_knowledge().build() {
+            Ok(svc) => panic!("should have failed conflicting protocols but was [{:?}]", svc),
+            Err(Error::ConflictingOptions(_)) => {}
+            Err(error) => panic!("expected ConflictingOptions but was [{:?}]", error),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn reject_zero_max_concurrent() {
+        init();
+        let builder = || {
+            ReqwestJsonService::builder()
+                .url("http://www.foo.net/reject_zero_max_concurrent")
+                .max_concurrent(0)
+        };
+
+        match builder().build() {
+            Ok(svc) => panic!("should have failed a cap of no requests but was [{:?}]", svc),
+            Err(Error::InvalidOption(_)) => {}
+            Err(error) => panic!("expected InvalidOption but was [{:?}]", error),
+        }
+        #[cfg(feature = "async")]
+        match builder().build_async() {
+            Ok(svc) => panic!("should have failed a cap of no requests but was [{:?}]", svc),
+            Err(Error::InvalidOption(_)) => {}
+            Err(error) => panic!("expected InvalidOption but was [{:?}]", error),
+        }
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Profile {
+        name: String,
+    }
+
+    #[derive(Debug)]
+    struct FetchProfile {}
+
+    impl From<FetchProfile> for Request {
+        fn from(_: FetchProfile) -> Request {
+            Request::Get {
+                path: "".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for FetchProfile {
+        type TResponse = Profile;
+        type TError = ();
+    }
+
+    #[test]
+    fn coalesce_identical_requests_in_flight() {
+        init();
+        let mock = mock("GET", "/coalesce_identical_requests_in_flight")
+            .with_status(200)
+            .with_body_from_fn(|w| {
+                thread::sleep(Duration::from_millis(300));
+                w.write_all(br#"{"name":"foo"}"#)
+            })
+            .expect(1)
+            .create();
+
+        let svc = Arc::new(
+            ReqwestJsonService::builder()
+                .url("http://www.foo.net/coalesce_identical_requests_in_flight")
+                .single_flight()
+                .build()
+                .unwrap(),
+        );
+
+        let callers: Vec<_> = (0..4)
+            .map(|_| {
+                let svc = svc.clone();
+                thread::spawn(move || match svc.exec(FetchProfile {}) {
+                    ServiceResult::Ok (result) => result.name,
+                    ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+                    ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+                })
+            })
+            .collect();
+        for caller in callers {
+            assert_eq!("foo", caller.join().unwrap());
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn keep_flights_of_different_credentials_apart() {
+        init();
+        let mocks: Vec<_> = vec!["foo", "bar"]
+            .into_iter()
+            .map(|name| {
+                mock("GET", "/keep_flights_of_different_credentials_apart/v2")
+                    .match_header("authorization", format!("Bearer {}", name).as_str())
+                    .with_status(200)
+                    .with_body_from_fn(move |w| {
+                        thread::sleep(Duration::from_millis(300));
+                        write!(w, r#"{{"name":"{}"}}"#, name)
+                    })
+                    .expect(1)
+                    .create()
+            })
+            .collect();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/keep_flights_of_different_credentials_apart")
+            .single_flight()
+            .build()
+            .unwrap();
+
+        let callers: Vec<_> = vec!["foo", "bar"]
+            .into_iter()
+            .map(|name| {
+                let scoped = svc.scoped("v2").unwrap().bearer_auth(name);
+                thread::spawn(move || match scoped.exec(FetchProfile {}) {
+                    ServiceResult::Ok (result) => assert_eq!(name, result.name),
+                    ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+                    ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+                })
+            })
+            .collect();
+        for caller in callers {
+            caller.join().unwrap();
+        }
+        for mock in mocks {
+            mock.assert();
+        }
+    }
+
+    /// Signs each request with a new nonce
+    struct Nonce(Arc<AtomicUsize>);
+
+    impl Signer for Nonce {
+        fn sign(
+            &self,
+            _method: &reqwest::Method,
+            _url: &url::Url,
+            _headers: &HeaderMap,
+            _body: Option<&[u8]>,
+        ) -> Vec<(String, String)> {
+            let nonce = self.0.fetch_add(1, Ordering::SeqCst);
+            vec![("x-nonce".to_owned(), nonce.to_string())]
+        }
+    }
+
+    #[test]
+    fn coalesce_requests_signed_with_nonces() {
+        init();
+        let mock = mock("GET", "/coalesce_requests_signed_with_nonces")
+            .match_header("x-nonce", "0")
+            .with_status(200)
+            .with_body_from_fn(|w| {
+                thread::sleep(Duration::from_millis(300));
+                w.write_all(br#"{"name":"foo"}"#)
+            })
+            .expect(1)
+            .create();
+
+        let signed = Arc::new(AtomicUsize::new(0));
+        let svc = Arc::new(
+            ReqwestJsonService::builder()
+                .url("http://www.foo.net/coalesce_requests_signed_with_nonces")
+                .single_flight()
+                .signer(Nonce(signed.clone()))
+                .build()
+                .unwrap(),
+        );
+
+        let callers: Vec<_> = (0..4)
+            .map(|_| {
+                let svc = svc.clone();
+                thread::spawn(move || match svc.exec(FetchProfile {}) {
+                    ServiceResult::Ok (result) => result.name,
+                    ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+                    ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+                })
+            })
+            .collect();
+        for caller in callers {
+            assert_eq!("foo", caller.join().unwrap());
+        }
+        // Only the request actually sent is signed
+        assert_eq!(1, signed.load(Ordering::SeqCst));
+        mock.assert();
+    }
+
+    #[test]
+    fn revalidate_cached_response_with_etag() {
+        init();
+        let fresh = mock("GET", "/revalidate_cached_response_with_etag")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("etag", r#""v1""#)
+            .with_body(r#"{"name":"foo"}"#)
+            .expect(1)
+            .create();
+        let not_modified = mock("GET", "/revalidate_cached_response_with_etag")
+            .match_header("if-none-match", r#""v1""#)
+            .with_status(304)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/revalidate_cached_response_with_etag")
+            .etag_cache()
+            .build()
+            .unwrap();
+
+        for expected_status in &[200, 304] {
+            match svc.exec_response(FetchProfile {}) {
+                ResponseResult::Ok (response) => {
+                    assert_eq!(*expected_status, response.status);
+                    assert_eq!("foo", response.body.name);
+                }
+                ResponseResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+                ResponseResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+            }
+        }
+        fresh.assert();
+        not_modified.assert();
+    }
+
+    #[test]
+    fn send_binary_requests_unconditionally() {
+        init();
+        let fresh = mock("GET", "/send_binary_requests_unconditionally")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("etag", r#""v1""#)
+            .with_body(r#"{"name":"foo"}"#)
+            .expect(2)
+            .create();
+        let not_modified = mock("GET", "/send_binary_requests_unconditionally")
+            .match_header("if-none-match", r#""v1""#)
+            .with_status(304)
+            .expect(0)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/send_binary_requests_unconditionally")
+            .etag_cache()
+            .build()
+            .unwrap();
+
+        match svc.exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => assert_eq!("foo", result.name),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        match svc.exec_bytes(FetchProfile {}) {
+            ResponseResult::Ok (response) => assert_eq!(br#"{"name":"foo"}"#.to_vec(), response.body),
+            ResponseResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ResponseResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        fresh.assert();
+        not_modified.assert();
+    }
+
+    thread_local! {
+        static CORRELATION_ID: std::cell::RefCell<Option<String>> = std::cell::RefCell::new(None);
+    }
+
+    #[test]
+    fn forward_correlation_id() {
+        init();
+        let forwarded = mock("GET", "/forward_correlation_id")
+            .match_header("x-correlation-id", "abc-123")
+            .with_status(200)
+            .with_body(r#"{"name":"foo"}"#)
+            .expect(1)
+            .create();
+        let omitted = mock("GET", "/forward_correlation_id")
+            .match_header("x-correlation-id", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body(r#"{"name":"bar"}"#)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/forward_correlation_id")
+            .correlation_id("X-Correlation-ID", || CORRELATION_ID.with(|id| id.borrow().clone()))
+            .build()
+            .unwrap();
+
+        CORRELATION_ID.with(|id| *id.borrow_mut() = Some("abc-123".to_owned()));
+        match svc.exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => assert_eq!("foo", result.name),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        CORRELATION_ID.with(|id| *id.borrow_mut() = None);
+        match svc.exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => assert_eq!("bar", result.name),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        forwarded.assert();
+        omitted.assert();
+    }
+
+    #[test]
+    fn reject_unexpected_content_type_when_strict() {
+        init();
+        let html = mock("GET", "/reject_unexpected_content_type_when_strict/html")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"{"name":"foo"}"#)
+            .expect(1)
+            .create();
+        let json = mock("GET", "/reject_unexpected_content_type_when_strict/json")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.foo+json; charset=utf-8")
+            .with_body(r#"{"name":"foo"}"#)
+            .expect(1)
+            .create();
+
+        let strict = |url: &str| ReqwestJsonService::builder().url(url).strict_content_type().build().unwrap();
+
+        match strict("http://www.foo.net/reject_unexpected_content_type_when_strict/html").exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => panic!("should have rejected the html response but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail (Error::UnexpectedContentType { got }, None) => assert_eq!(Some("text/html".to_owned()), got),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("expected UnexpectedContentType but was [{:?}] with [{:?}]", service_error, maybe_api_serde),
+        }
+        match strict("http://www.foo.net/reject_unexpected_content_type_when_strict/json").exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => assert_eq!("foo", result.name),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        html.assert();
+        json.assert();
+    }
+
+    #[test]
+    fn unwrap_payload_from_envelope() {
+        init();
+        let mock = mock("GET", "/unwrap_payload_from_envelope")
+            .with_status(200)
+            .with_body(r#"{"data":{"profile":{"name":"foo"}},"meta":{"page":1}}"#)
+            .expect(2)
+            .create();
+
+        let builder = || ReqwestJsonService::builder().url("http://www.foo.net/unwrap_payload_from_envelope");
+
+        let svc = builder().envelope("/data/profile").build().unwrap();
+        match svc.exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => assert_eq!("foo", result.name),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        let svc = builder().envelope("payload").build().unwrap();
+        match svc.exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => panic!("should have failed without the envelope field but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail (Error::InvalidPayload { serde_error, .. }, _) => assert!(serde_error.to_string().contains("/payload"), "unexpected error [{}]", serde_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("expected InvalidPayload but was [{:?}] with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn fail_reading_response_over_limit() {
+        init();
+        let mock = mock("GET", "/fail_reading_response_over_limit")
+            .with_status(200)
+            .with_body(format!(r#"{{"name":"{}"}}"#, "x".repeat(64)))
+            .expect(2)
+            .create();
+
+        let builder = || ReqwestJsonService::builder().url("http://www.foo.net/fail_reading_response_over_limit");
+
+        let svc = builder().max_response_bytes(16).build().unwrap();
+        match svc.exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => panic!("should have failed the oversized body but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail (Error::ResponseTooLarge { limit }, None) => assert_eq!(16, limit),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("expected ResponseTooLarge but was [{:?}] with [{:?}]", service_error, maybe_api_serde),
+        }
+        let svc = builder().max_response_bytes(1024).build().unwrap();
+        match svc.exec(FetchProfile {}) {
+            ServiceResult::Ok (result) => assert_eq!(64, result.name.len()),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn send_with_each_http_version() {
+        init();
+        let mock = mock("GET", "/send_with_each_http_version")
+            .with_status(200)
+            .with_body("{}")
+            .expect(2)
+            .create();
+        let builder = || {
+            ReqwestJsonService::builder()
+                .url("http://www.foo.net/send_with_each_http_version")
+                .timeout(Duration::from_secs(5))
+        };
+
+        for version in &[HttpVersion::Http1, HttpVersion::Http2, HttpVersion::Auto] {
+            #[cfg(feature = "async")]
+            if let Err(error) = builder().http_version(*version).build_async() {
+                panic!("should have built async [{:?}] service but failed with [{:?}]", version, error);
+            }
+            let result = builder().http_version(*version).build().unwrap().exec(Unit {});
+            // The mock server only speaks http1, which plain http negotiates down to
+            match (*version, result) {
+                (HttpVersion::Http2, ServiceResult::Ok (result)) => panic!("should have failed speaking http2 to an http1 server but was [{:?}]", result),
+                (HttpVersion::Http2, ServiceResult::Err (service_error, api_error)) => panic!("should have failed speaking http2 to an http1 server but was [{:?}] and [{:?}]", service_error, api_error),
+                (HttpVersion::Http2, ServiceResult::Fail (_, None)) => {},
+                (_, ServiceResult::Ok (_)) => {},
+                (_, ServiceResult::Err (service_error, api_error)) => panic!("should not have failed [{:?}] with [{:?}] or had an api error to parse but was [{:?}]", version, service_error, api_error),
+                (_, ServiceResult::Fail (service_error, maybe_api_serde)) => panic!("should not have failed [{:?}] with [{:?}] or had an api error to parse but failed with [{:?}]", version, service_error, maybe_api_serde),
+            }
+        }
+        // Agreeing toggles are redundant rather than conflicting
+        if let Err(error) = builder().http_version(HttpVersion::Http1).http1_only().build() {
+            panic!("should have built http1 only service but failed with [{:?}]", error);
+        }
+        for conflicting in vec![
+            builder().http_version(HttpVersion::Http1).http2_prior_knowledge(),
+            builder().http_version(HttpVersion::Http2).http1_only(),
+            builder().http_version(HttpVersion::Auto).http1_only(),
+        ] {
+            match conflicting.build() {
+                Ok(svc) => panic!("should have failed conflicting versions but was [{:?}]", svc),
+                Err(Error::ConflictingOptions(_)) => {}
+                Err(error) => panic!("expected ConflictingOptions but was [{:?}]", error),
+            }
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn send_through_proxy() {
+        init();
+        let mock = mock("GET", "/send_through_proxy")
+            .with_status(200)
+            .with_body("{}")
+            .expect(0)
+            .create();
+
+        // Nothing listens on the proxy's port so the request never reaches the mock server
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/send_through_proxy")
+            .proxy("http://127.0.0.1:1")
+            .proxy_basic_auth("user", "secret")
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (result) => panic!("should have failed connecting to the proxy but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should have failed connecting to the proxy but was [{:?}] and [{:?}]", service_error, api_error),
+            ServiceResult::Fail (Error::ConnectFailed { .. }, None) => {},
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("expected ConnectFailed but was [{:?}] and [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn authenticate_with_proxy() {
+        init();
+        // Proxied requests carry the whole url, the mock server stands in for the proxy itself
+        let mock = mock("GET", mockito::Matcher::Regex("/authenticate_with_proxy$".to_owned()))
+            .match_header("proxy-authorization", "Basic dXNlcjpzZWNyZXQ=")
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/authenticate_with_proxy")
+            .proxy(&mockito::server_url())
+            .proxy_basic_auth("user", "secret")
+            .build()
+            .unwrap();
+
+        match svc.exec(Unit {}) {
+            ServiceResult::Ok (_) => {},
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn fail_build_with_proxy_auth_but_no_proxy() {
+        init();
+        let result = ReqwestJsonService::builder()
+            .url("http://www.foo.net/fail_build_with_proxy_auth_but_no_proxy")
+            .proxy_basic_auth("user", "secret")
+            .build();
+
+        match result {
+            Ok(svc) => panic!("should have failed proxy credentials without a proxy but was [{:?}]", svc),
+            Err(Error::InvalidOption(_)) => {}
+            Err(error) => panic!("expected InvalidOption but was [{:?}]", error),
+        }
+    }
+
+    #[test]
+    fn fail_build_with_invalid_proxy() {
+        init();
+        let result = ReqwestJsonService::builder()
+            .url("http://www.foo.net/fail_build_with_invalid_proxy")
+            .proxy("http://proxy foo net")
+            .build();
+
+        match result {
+            Ok(svc) => panic!("should have failed invalid proxy but was [{:?}]", svc),
+            Err(Error::InvalidProxy(_)) => {}
+            Err(error) => panic!("expected InvalidProxy but was [{:?}]", error),
+        }
+    }
+
+    #[test]
+    fn fail_build_with_invalid_identity() {
+        init();
+        let result = ReqwestJsonService::builder()
+            .url("http://www.foo.net/fail_build_with_invalid_identity")
+            .identity_pkcs12(b"not a pkcs12 archive", "secret")
+            .build();
+
+        match result {
+            Ok(svc) => panic!("should have failed invalid identity but was [{:?}]", svc),
+            Err(Error::InvalidIdentity(_)) => {}
+            Err(error) => panic!("expected InvalidIdentity but was [{:?}]", error),
+        }
+    }
+
+    #[test]
+    fn fail_build_with_invalid_pem_identity() {
+        init();
+        let result = ReqwestJsonService::builder()
+            .url("http://www.foo.net/fail_build_with_invalid_pem_identity")
+            .identity_pem(b"not a certificate", b"not a key")
+            .build();
+
+        match result {
+            Ok(svc) => panic!("should have failed invalid identity but was [{:?}]", svc),
+            Err(Error::InvalidIdentity(_)) => {}
+            Err(error) => panic!("expected InvalidIdentity but was [{:?}]", error),
+        }
+    }
+
+    const ROOT_CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----\n\
+MIIBizCCATGgAwIBAgIUItsniLAyeBVAUG6wSq1XctMxz3kwCgYIKoZIzj0EAwIw\n\
+GjEYMBYGA1UEAwwPZ2F0ZXdheS10ZXN0LWNhMCAXDTI2MTAxNTA3MTYzNVoYDzIx\n\
+MjYwOTIxMDcxNjM1WjAaMRgwFgYDVQQDDA9nYXRld2F5LXRlc3QtY2EwWTATBgcq\n\
+hkjOPQIBBggqhkjOPQMBBwNCAARlAGm82aXYnfi7W9wQ4jGv2s5QdrMw9ebB/Pad\n\
+frAYOwk1jl9UMDYluYQ8YXiOO7kbZuRR69lijEOAzIcbLfbwo1MwUTAdBgNVHQ4E\n\
+FgQUJRJeT4RUp+dhIkHZSZrqxQMegfMwHwYDVR0jBBgwFoAUJRJeT4RUp+dhIkHZ\n\
+SZrqxQMegfMwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNIADBFAiEAktzv\n\
+HUwiIWtkNxoxkIwMCidhc62fq3I3EDU5Cdy8lnICIBwLVTaEQpZdLG+w1ejpw5Cm\n\
+VElGtb4sHXQIkPKIxzXL\n\
+-----END CERTIFICATE-----";
+
+    #[test]
+    fn build_with_root_certificate() {
+        init();
+        let result = ReqwestJsonService::builder()
+            .url("http://www.foo.net/build_with_root_certificate")
+            .add_root_certificate(ROOT_CERTIFICATE.as_bytes())
+            .build();
+
+        if let Err(error) = result {
+            panic!("should have built service with root certificate but failed with [{:?}]", error);
+        }
+    }
+
+    #[test]
+    fn fail_build_with_invalid_root_certificate() {
+        init();
+        let result = ReqwestJsonService::builder()
+            .url("http://www.foo.net/fail_build_with_invalid_root_certificate")
+            .add_root_certificate(b"not a certificate")
+            .build();
+
+        match result {
+            Ok(svc) => panic!("should have failed invalid certificate but was [{:?}]", svc),
+            Err(Error::InvalidCertificate(_)) => {}
+            Err(error) => panic!("expected InvalidCertificate but was [{:?}]", error),
+        }
+    }
+
+    #[test]
+    fn build_accepting_invalid_certs() {
+        init();
+        let result = ReqwestJsonService::builder()
+            .url("http://www.foo.net/build_accepting_invalid_certs")
+            .danger_accept_invalid_certs(true)
+            .build();
+
+        if let Err(error) = result {
+            panic!("should have built service accepting invalid certs but failed with [{:?}]", error);
+        }
+    }
+
+    /// Error type which must never be deserialized
+    #[derive(Debug)]
+    struct Unparsed {}
+
+    impl<'de> serde::Deserialize<'de> for Unparsed {
+        fn deserialize<D: serde::Deserializer<'de>>(_: D) -> Result<Self, D::Error> {
+            panic!("error payload should not have been parsed")
+        }
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Lookup {}
+
+    impl From<Lookup> for Request {
+        fn from(_: Lookup) -> Request {
+            Request::Get {
+                path: "".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for Lookup {
+        type TResponse = Unit;
+        type TError = Unparsed;
+    }
+
+    #[test]
+    fn skip_parsing_error_payloads() {
+        init();
+        let mock = mock("GET", "/skip_parsing_error_payloads")
+            .with_status(404)
+            .with_body(r#"{"reason":"missing"}"#)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/skip_parsing_error_payloads")
+            .skip_error_payloads()
+            .build()
+            .unwrap();
+
+        match svc.exec(Lookup {}) {
+            ServiceResult::Ok(result) => panic!("should have detected invalid status but was [{:?}]", result),
+            ServiceResult::Err(service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}] and [{:?}]", service_error, api_error),
+            ServiceResult::Fail(service_error, maybe_api_serde) => {
+                match service_error {
+                    Error::ResultFailed { status, .. } => assert_eq!(404, status),
+                    _ => panic!("expected ResultFailed related error but was [{:?}]", service_error),
+                }
+                assert!(maybe_api_serde.is_none(), "should not have parsed error payload [{:?}]", maybe_api_serde);
+            }
+        }
+        mock.assert();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Validate {}
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Validation {
+        field: String,
+    }
+
+    impl From<Validate> for Request {
+        fn from(_: Validate) -> Request {
+            Request::Get {
+                path: "".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for Validate {
+        type TResponse = Validation;
+        type TError = ();
+    }
+
+    #[test]
+    fn decode_gzip_encoded_response() {
+        init();
+        // `{"field":"name"}` gzip compressed
+        let body: &[u8] = &[
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56, 0x4a, 0xcb,
+            0x4c, 0xcd, 0x49, 0x51, 0xb2, 0x52, 0xca, 0x4b, 0xcc, 0x4d, 0x55, 0xaa, 0x05, 0x00,
+            0x7c, 0x63, 0xdd, 0xfe, 0x10, 0x00, 0x00, 0x00,
+        ];
+        let mock = mock("GET", "/decode_gzip_encoded_response")
+            .match_header("accept-encoding", mockito::Matcher::Regex("gzip".to_owned()))
+            .with_status(200)
+            .with_header("content-encoding", "gzip")
+            .with_body(body)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/decode_gzip_encoded_response")
+            .decompress(true)
+            .build()
+            .unwrap();
+
+        match svc.exec(Validate {}) {
+            ServiceResult::Ok (result) => assert_eq!("name", result.field),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn persist_cookies_between_requests() {
+        init();
+        let login = mock("GET", "/persist_cookies_between_requests/login")
+            .with_status(200)
+            .with_header("set-cookie", "session=abc123; Path=/")
+            .expect(1)
+            .create();
+        let profile = mock("GET", "/persist_cookies_between_requests/profile")
+            .match_header("cookie", "session=abc123")
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/persist_cookies_between_requests/")
+            .cookie_store(true)
+            .build()
+            .unwrap();
+
+        assert!(svc.health_check("login").unwrap());
+        assert!(svc.health_check("profile").unwrap(), "session cookie should have been sent");
+        login.assert();
+        profile.assert();
+    }
+
+    #[test]
+    fn parse_accepted_status_as_response() {
+        init();
+        let mock = mock("GET", "/parse_accepted_status_as_response")
+            .with_status(422)
+            .with_body(r#"{"field":"name"}"#)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/parse_accepted_status_as_response")
+            .accept_status(422)
+            .build()
+            .unwrap();
+
+        match svc.exec(Validate {}) {
+            ServiceResult::Ok (result) => assert_eq!("name", result.field),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn display_configured_status_message() {
+        init();
+        let mock = mock("GET", "/display_configured_status_message")
+            .with_status(503)
+            .with_body("upstream down")
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/display_configured_status_message")
+            .status_message(503, "Service temporarily unavailable")
+            .build()
+            .unwrap();
+
+        match svc.exec(Validate {}) {
+            ServiceResult::Ok (result) => panic!("should have failed with the status but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error @ Error::ResultFailed { .. }, None) => {
+                let display = format!("{}", service_error);
+                assert!(display.starts_with("Service temporarily unavailable [503]"), "missing message in [{}]", display);
+            }
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("expected ResultFailed but was [{:?}] with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Hero {
+        name: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct GraphQlFailure {
+        errors: Vec<GraphQlError>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct GraphQlError {
+        message: String,
+    }
+
+    #[derive(Debug)]
+    struct FindHero {}
+
+    impl From<FindHero> for Request {
+        fn from(_: FindHero) -> Request {
+            Request::Post {
+                path: "".to_owned(),
+                body: r#"{"query":"{ hero { name } }"}"#.to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for FindHero {
+        type TResponse = Hero;
+        type TError = GraphQlFailure;
+    }
+
+    #[test]
+    fn route_graphql_errors_to_error_payload() {
+        init();
+        let mock = mock("POST", "/route_graphql_errors_to_error_payload")
+            .with_status(200)
+            .with_body(r#"{"data":null,"errors":[{"message":"hero not found"}]}"#)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/route_graphql_errors_to_error_payload")
+            .graphql()
+            .envelope("data")
+            .build()
+            .unwrap();
+
+        match svc.exec(FindHero {}) {
+            ServiceResult::Ok (result) => panic!("should have failed with the graphql errors but was [{:?}]", result),
+            ServiceResult::Err (Error::GraphQlErrors { .. }, api_error) => assert_eq!("hero not found", api_error.errors[0].message),
+            ServiceResult::Err (service_error, api_error) => panic!("expected GraphQlErrors but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should have parsed the api error but failed with [{:?}] and [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+}
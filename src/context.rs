@@ -0,0 +1,218 @@
+use std::time::Duration;
+
+use crate::{Options, Request};
+
+/// Correlation header used for `RequestContext::correlation_id` when the service doesn't
+/// configure one with `ReqwestJsonServiceBuilder::correlation_id`
+const CORRELATION_ID: &str = "X-Correlation-ID";
+
+/// Per call overrides applied to a request by `exec_with_context`, so call sites can customize a
+/// request with a single argument rather than wrapping it piece by piece
+#[derive(Clone, Debug, Default)]
+pub struct RequestContext {
+    headers: Vec<(String, String)>,
+    timeout: Option<Duration>,
+    correlation_id: Option<String>,
+    idempotency_key: Option<String>,
+    extensions: Vec<(String, String)>,
+}
+
+impl RequestContext {
+    pub fn new() -> Self {
+        RequestContext::default()
+    }
+
+    /// Send the header along with the request, see `Request::with_header`
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Fail the request if it takes longer than `timeout`, see `Request::with_timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send the id in the service's correlation header, or `X-Correlation-ID` when it has none,
+    /// in place of any id the service would otherwise forward
+    pub fn correlation_id(mut self, id: &str) -> Self {
+        self.correlation_id = Some(id.to_owned());
+        self
+    }
+
+    /// Send the key as the request's `Idempotency-Key`, see `Request::with_idempotency_key`
+    pub fn idempotency_key(mut self, key: &str) -> Self {
+        self.idempotency_key = Some(key.to_owned());
+        self
+    }
+
+    /// Attach the metadata to the request for interceptors, see `Request::with_extension`
+    pub fn extension(mut self, key: &str, value: &str) -> Self {
+        self.extensions.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Wrap the request with the overrides
+    pub(crate) fn apply(self, options: &Options, mut req: Request) -> Request {
+        for (name, value) in &self.headers {
+            req = req.with_header(name, value);
+        }
+        if let Some(id) = &self.correlation_id {
+            let header = options
+                .correlation
+                .as_ref()
+                .map_or(CORRELATION_ID, |(header, _)| header.as_str());
+            req = req.with_header(header, id);
+        }
+        if let Some(key) = &self.idempotency_key {
+            req = req.with_idempotency_key(key);
+        }
+        for (key, value) in &self.extensions {
+            req = req.with_extension(key, value);
+        }
+        match self.timeout {
+            Some(timeout) => req.with_timeout(timeout),
+            None => req,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::thread;
+    use std::time::Duration;
+
+    use gateway::{Endpoint, ServiceResult};
+    use mockito::mock;
+
+    use super::RequestContext;
+    #[cfg(feature = "blocking")]
+    use crate::Interceptor;
+    #[cfg(feature = "async")]
+    use crate::AsyncReqwestJsonService;
+    #[cfg(feature = "blocking")]
+    use crate::ReqwestJsonService;
+    use crate::{Error, Request};
+
+    fn init() {
+        crate::testing::init();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Report {
+        rows: u32,
+    }
+
+    #[derive(Debug)]
+    struct FetchReport {}
+
+    impl From<FetchReport> for Request {
+        fn from(_: FetchReport) -> Request {
+            Request::Get {
+                path: "".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for FetchReport {
+        type TResponse = Report;
+        type TError = ();
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn apply_context_to_request() {
+        init();
+        let mock = mock("GET", "/apply_context_to_request")
+            .match_header("x-tenant", "acme")
+            .match_header("x-correlation-id", "abc-123")
+            .with_status(200)
+            .with_body_from_fn(|w| {
+                thread::sleep(Duration::from_millis(300));
+                w.write_all(br#"{"rows":3}"#)
+            })
+            .expect(2)
+            .create();
+
+        let svc = ReqwestJsonService::with_url("http://www.foo.net/apply_context_to_request").unwrap();
+        let ctx = RequestContext::new().header("X-Tenant", "acme").correlation_id("abc-123");
+
+        match svc.exec_with_context(FetchReport {}, ctx.clone()) {
+            ServiceResult::Ok (result) => assert_eq!(3, result.rows),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        match svc.exec_with_context(FetchReport {}, ctx.timeout(Duration::from_millis(50))) {
+            ServiceResult::Ok (result) => panic!("should have timed out but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}] with [{:?}]", service_error, api_error),
+            // Depending on when the deadline elapses the body may already be streaming
+            ServiceResult::Fail (Error::TimedOut { reqwest_error: err, .. }, None) | ServiceResult::Fail (Error::ReadBodyFailed(err), None) => assert!(err.is_timeout(), "expected a timeout error but was [{:?}]", err),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("expected TimedOut related error but was [{:?}] with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    /// Sends the tenant the request was made for as a header
+    #[cfg(feature = "blocking")]
+    struct Tenant;
+
+    #[cfg(feature = "blocking")]
+    impl Interceptor for Tenant {
+        fn before(&self, req: &mut Request) {
+            if let Some(tenant) = req.extension("tenant").map(str::to_owned) {
+                let original = std::mem::replace(req, Request::Get { path: String::new() });
+                *req = original.with_header("X-Tenant", &tenant);
+            }
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn pass_context_extensions_to_interceptors() {
+        init();
+        let mock = mock("GET", "/pass_context_extensions_to_interceptors")
+            .match_header("x-tenant", "acme")
+            .match_header("tenant", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body(r#"{"rows":3}"#)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/pass_context_extensions_to_interceptors")
+            .interceptor(Tenant)
+            .build()
+            .unwrap();
+
+        match svc.exec_with_context(FetchReport {}, RequestContext::new().extension("tenant", "acme")) {
+            ServiceResult::Ok (result) => assert_eq!(3, result.rows),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn apply_context_to_async_request() {
+        init();
+        let mock = mock("GET", "/apply_context_to_async_request")
+            .match_header("idempotency-key", "key-1")
+            .with_status(200)
+            .with_body(r#"{"rows":3}"#)
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url("http://www.foo.net/apply_context_to_async_request")
+            .unwrap();
+
+        match svc.exec_with_context(FetchReport {}, RequestContext::new().idempotency_key("key-1")).await {
+            ServiceResult::Ok (result) => assert_eq!(3, result.rows),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+
+use gateway::{Endpoint, ServiceResult};
+
+#[cfg(feature = "blocking")]
+use crate::ReqwestJsonService;
+use crate::{invalid_payload, is_unit, Error, RecordingService, Request, Response, ResponseResult};
+
+/// Object safe counterpart of `gateway::Service`, which can't be made into a trait object as
+/// `exec` is generic over the request, so services can be swapped at runtime behind a
+/// `Box<dyn DynService>`, e.g. a `RecordingService` in place of a `ReqwestJsonService` in tests
+///
+/// Payloads cross the trait as json values, processed with the service's own configuration, and
+/// `exec` on the trait object deserializes them into the endpoint's types.
+pub trait DynService: Send + Sync {
+    /// Execute the request parsing the successful payload, or the error payload, as json values
+    fn exec_value(&self, req: Request) -> ResponseResult<serde_json::Value, serde_json::Value>;
+}
+
+impl dyn DynService {
+    /// Execute the request as `Service::exec` does, through whichever service is behind the
+    /// trait object
+    pub fn exec<TRequest>(&self, req: TRequest) -> ServiceResult<TRequest, Error, serde_json::Error>
+    where
+        TRequest: Into<Request> + Endpoint + fmt::Debug,
+    {
+        match self.exec_value(req.into()) {
+            ResponseResult::Ok(Response { headers, body, text, .. }) => {
+                match serde_json::from_value::<TRequest::TResponse>(body) {
+                    Ok(body) => ServiceResult::Ok(body),
+                    Err(serde_error) => {
+                        let content_type = headers
+                            .get(reqwest::header::CONTENT_TYPE)
+                            .and_then(|value| value.to_str().ok());
+                        ServiceResult::Fail(invalid_payload(serde_error, &text, content_type), None)
+                    }
+                }
+            }
+            // Endpoints without an error type never parse one, see `Options::parse_error`
+            ResponseResult::Err(err, _) if is_unit::<TRequest::TError>() => {
+                ServiceResult::Fail(err, None)
+            }
+            ResponseResult::Err(err, value) => match serde_json::from_value(value) {
+                Ok(api_error) => ServiceResult::Err(err, api_error),
+                Err(serde_error) => ServiceResult::Fail(err, Some(serde_error)),
+            },
+            // Nor do they report an error payload which isn't json
+            ResponseResult::Fail(err, Some(_)) if is_unit::<TRequest::TError>() => {
+                ServiceResult::Fail(err, None)
+            }
+            ResponseResult::Fail(err, maybe_serde_err) => ServiceResult::Fail(err, maybe_serde_err),
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl DynService for ReqwestJsonService {
+    fn exec_value(&self, req: Request) -> ResponseResult<serde_json::Value, serde_json::Value> {
+        self.exchange(req).0
+    }
+}
+
+impl DynService for RecordingService {
+    fn exec_value(&self, req: Request) -> ResponseResult<serde_json::Value, serde_json::Value> {
+        self.respond(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gateway::{Endpoint, ServiceResult};
+    #[cfg(feature = "blocking")]
+    use mockito::mock;
+
+    use super::DynService;
+    #[cfg(feature = "blocking")]
+    use crate::ReqwestJsonService;
+    use crate::{Error, RecordingService, Request};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Balance {
+        amount: u32,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct BalanceError {
+        reason: String,
+    }
+
+    #[derive(Debug)]
+    struct FetchBalance {}
+
+    impl From<FetchBalance> for Request {
+        fn from(_: FetchBalance) -> Request {
+            Request::Get {
+                path: "balance".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for FetchBalance {
+        type TResponse = Balance;
+        type TError = BalanceError;
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn exec_through_boxed_service() {
+        crate::testing::init();
+        let mock = mock("GET", "/exec_through_boxed_service/balance")
+            .with_status(200)
+            .with_body(r#"{"amount":10}"#)
+            .expect(1)
+            .create();
+
+        let services: Vec<Box<dyn DynService>> = vec![
+            Box::new(ReqwestJsonService::with_url("http://www.foo.net/exec_through_boxed_service/").unwrap()),
+            Box::new(RecordingService::new(r#"{"amount":20}"#)),
+        ];
+
+        let amounts: Vec<u32> = services
+            .iter()
+            .map(|svc| match svc.exec(FetchBalance {}) {
+                ServiceResult::Ok (result) => result.amount,
+                ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+                ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+            })
+            .collect();
+        assert_eq!(vec![10, 20], amounts);
+        mock.assert();
+    }
+
+    #[derive(Debug)]
+    struct Ping {}
+
+    impl From<Ping> for Request {
+        fn from(_: Ping) -> Request {
+            Request::Get {
+                path: "ping".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for Ping {
+        type TResponse = Balance;
+        type TError = ();
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn skip_unparsable_error_payload_through_boxed_service() {
+        crate::testing::init();
+        let mock = mock("GET", "/skip_unparsable_error_payload_through_boxed_service/ping")
+            .with_status(500)
+            .with_header("content-type", "text/html")
+            .with_body("<html><body>Internal Server Error</body></html>")
+            .expect(1)
+            .create();
+
+        let svc: Box<dyn DynService> = Box::new(
+            ReqwestJsonService::with_url("http://www.foo.net/skip_unparsable_error_payload_through_boxed_service/")
+                .unwrap(),
+        );
+
+        match svc.exec(Ping {}) {
+            ServiceResult::Ok (result) => panic!("should have failed with the status but was [{:?}]", result),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have had an api error to parse but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail (Error::ResultFailed { status, .. }, None) => assert_eq!(500, status),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("expected ResultFailed without an api error but was [{:?}] with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[test]
+    fn parse_api_error_through_boxed_service() {
+        let svc: Box<dyn DynService> =
+            Box::new(RecordingService::responding(402, r#"{"reason":"overdrawn"}"#));
+
+        match svc.exec(FetchBalance {}) {
+            ServiceResult::Ok (result) => panic!("should have failed with the canned status but was [{:?}]", result),
+            ServiceResult::Err (Error::ResultFailed { status, .. }, api_error) => {
+                assert_eq!(402, status);
+                assert_eq!("overdrawn", api_error.reason);
+            }
+            ServiceResult::Err (service_error, api_error) => panic!("expected ResultFailed but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should have parsed the api error but failed with [{:?}] and [{:?}]", service_error, maybe_api_serde),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+
+use crate::{display_url, Error};
+
+/// Most responses the cache remembers, the least recently used is evicted to make room for more
+pub(crate) const CAPACITY: usize = 1024;
+
+/// Bodies of `GET` responses which carried an `ETag`, keyed by their url without credentials,
+/// so repeated requests can be made conditional and a `304 Not Modified` answered from memory
+pub(crate) struct EtagCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+/// Cached responses by url, each stamped with when it was last used
+#[derive(Default)]
+struct Entries {
+    by_url: HashMap<String, Entry>,
+    clock: u64,
+}
+
+struct Entry {
+    etag: HeaderValue,
+    body: String,
+    used: u64,
+}
+
+impl Default for EtagCache {
+    fn default() -> Self {
+        EtagCache::with_capacity(CAPACITY)
+    }
+}
+
+impl Entries {
+    /// Cached response for the url, marking it as the most recently used
+    fn get(&mut self, url: &str) -> Option<&Entry> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.by_url.get_mut(url).map(|entry| {
+            entry.used = clock;
+            &*entry
+        })
+    }
+}
+
+impl EtagCache {
+    fn with_capacity(capacity: usize) -> Self {
+        EtagCache {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Add `If-None-Match` with the cached `ETag` of the url, unless the request sets its own
+    pub(crate) fn condition(&self, url: &url::Url, mut headers: HeaderMap) -> HeaderMap {
+        if !headers.contains_key(IF_NONE_MATCH) {
+            if let Some(entry) = self.entries().get(&display_url(url.as_str())) {
+                headers.insert(IF_NONE_MATCH, entry.etag.clone());
+            }
+        }
+        headers
+    }
+
+    /// Answer a `304` with the cached body and cache the body of a `200` which has an `ETag`
+    pub(crate) fn revalidate(
+        &self,
+        url: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        text: Result<String, Error>,
+    ) -> Result<String, Error> {
+        match (status, headers.get(ETAG), &text) {
+            (StatusCode::NOT_MODIFIED, _, _) => match self.entries().get(url) {
+                Some(entry) => Ok(entry.body.to_owned()),
+                None => text,
+            },
+            (StatusCode::OK, Some(etag), Ok(body)) => {
+                self.insert(url, etag.clone(), body.to_owned());
+                text
+            }
+            _ => text,
+        }
+    }
+
+    /// Remember the response, evicting the least recently used one when the cache is full
+    fn insert(&self, url: &str, etag: HeaderValue, body: String) {
+        let mut entries = self.entries();
+        if !entries.by_url.contains_key(url) && entries.by_url.len() >= self.capacity {
+            let evicted = entries
+                .by_url
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.to_owned());
+            if let Some(evicted) = evicted {
+                entries.by_url.remove(&evicted);
+            }
+        }
+        entries.clock += 1;
+        let used = entries.clock;
+        entries.by_url.insert(url.to_owned(), Entry { etag, body, used });
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_NONE_MATCH};
+    use reqwest::StatusCode;
+
+    use super::EtagCache;
+
+    fn cache(etags: &EtagCache, url: &str) {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        etags
+            .revalidate(url, StatusCode::OK, &headers, Ok(url.to_owned()))
+            .unwrap();
+    }
+
+    fn is_cached(etags: &EtagCache, url: &str) -> bool {
+        let url = url::Url::parse(url).unwrap();
+        etags.condition(&url, HeaderMap::new()).contains_key(IF_NONE_MATCH)
+    }
+
+    #[test]
+    fn evict_least_recently_used_response() {
+        let etags = EtagCache::with_capacity(2);
+        cache(&etags, "http://www.foo.net/a");
+        cache(&etags, "http://www.foo.net/b");
+        // Revalidating `a` leaves `b` as the least recently used
+        assert!(is_cached(&etags, "http://www.foo.net/a"));
+        cache(&etags, "http://www.foo.net/c");
+
+        assert!(is_cached(&etags, "http://www.foo.net/a"));
+        assert!(!is_cached(&etags, "http://www.foo.net/b"));
+        assert!(is_cached(&etags, "http://www.foo.net/c"));
+    }
+}
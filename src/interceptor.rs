@@ -0,0 +1,163 @@
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+use crate::{Error, Options, Request, Response};
+
+/// Cross-cutting transformation of every request made by a service and of the response it
+/// receives, e.g. signing requests, injecting correlation ids or masking response fields
+///
+/// Interceptors are invoked in the order they were added to the builder, `before` ahead of any
+/// `on_request` hook, and `after` once the body has been read but before it is validated and
+/// deserialized.  Only the body text is available in `after`, the `body` is always `()`.
+/// Metadata a caller attached with `Request::with_extension` or `RequestContext::extension` can
+/// be read in `before` with `Request::extension`.
+pub trait Interceptor: Send + Sync {
+    /// Adjust the request before it is sent
+    fn before(&self, _req: &mut Request) {}
+
+    /// Adjust the response, or the failure to receive it, before it is processed
+    fn after(&self, _result: &mut Result<Response<()>, Error>) {}
+}
+
+/// Run the response through the service's interceptors, keeping the original status if an
+/// interceptor replaces it with an invalid one
+pub(crate) fn intercept_response(
+    options: &Options,
+    status: StatusCode,
+    headers: HeaderMap,
+    text: Result<String, Error>,
+) -> (StatusCode, HeaderMap, Result<String, Error>) {
+    if options.interceptors.is_empty() {
+        return (status, headers, text);
+    }
+    let mut result = text.map(|text| Response {
+        status: status.as_u16(),
+        headers,
+        body: (),
+        length: text.len(),
+        text,
+    });
+    for interceptor in &options.interceptors {
+        interceptor.after(&mut result);
+    }
+    match result {
+        Ok(response) => (
+            StatusCode::from_u16(response.status).unwrap_or(status),
+            response.headers,
+            Ok(response.text),
+        ),
+        // Failures are reported without the response's headers
+        Err(err) => (status, HeaderMap::new(), Err(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "blocking")]
+    use gateway::Service;
+    use gateway::{Endpoint, ServiceResult};
+    use mockito::mock;
+
+    use super::Interceptor;
+    #[cfg(feature = "async")]
+    use crate::AsyncReqwestJsonService;
+    #[cfg(feature = "blocking")]
+    use crate::ReqwestJsonService;
+    use crate::{Error, Request, Response};
+
+    fn init() {
+        crate::testing::init();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Account {
+        owner: String,
+        number: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct FetchAccount {}
+
+    impl From<FetchAccount> for Request {
+        fn from(_: FetchAccount) -> Request {
+            Request::Get {
+                path: "".to_owned(),
+            }
+        }
+    }
+
+    impl Endpoint for FetchAccount {
+        type TResponse = Account;
+        type TError = ();
+    }
+
+    struct Correlate(&'static str);
+
+    impl Interceptor for Correlate {
+        fn before(&self, req: &mut Request) {
+            let original = std::mem::replace(req, Request::Get { path: String::new() });
+            *req = original.with_header("X-Correlation-ID", self.0);
+        }
+    }
+
+    struct MaskAccountNumbers;
+
+    impl Interceptor for MaskAccountNumbers {
+        fn after(&self, result: &mut Result<Response<()>, Error>) {
+            if let Ok(response) = result {
+                response.text = response.text.replace("12345678", "****5678");
+            }
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn intercept_requests_and_responses() {
+        init();
+        let mock = mock("GET", "/intercept_requests_and_responses")
+            .match_header("x-correlation-id", "abc-123")
+            .with_status(200)
+            .with_body(r#"{"owner":"foo","number":"12345678"}"#)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/intercept_requests_and_responses")
+            .interceptor(Correlate("abc-123"))
+            .interceptor(MaskAccountNumbers)
+            .build()
+            .unwrap();
+
+        match svc.exec(FetchAccount {}) {
+            ServiceResult::Ok (result) => assert_eq!("****5678", result.number),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn intercept_async_requests() {
+        init();
+        let mock = mock("GET", "/intercept_async_requests")
+            .match_header("x-correlation-id", "abc-123")
+            .with_status(200)
+            .with_body(r#"{"owner":"foo","number":"12345678"}"#)
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::builder()
+            .url("http://www.foo.net/intercept_async_requests")
+            .interceptor(Correlate("abc-123"))
+            .build_async()
+            .unwrap();
+
+        match svc.exec(FetchAccount {}).await {
+            ServiceResult::Ok (result) => assert_eq!("12345678", result.number),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+use gateway::{Endpoint, Service, ServiceResult};
+
+use crate::{
+    into_response_result, into_service_result, process_response, Error, Options, Request,
+    ResponseResult,
+};
+
+/// Url reported by errors from a `RecordingService`, as no request is actually sent
+const RECORDED_URL: &str = "recording://";
+
+/// Test double which records each request instead of sending it and answers every request with
+/// the same canned response, e.g. to test code built on the gateway without a mock server
+///
+/// The canned response is processed just as a real one would be, so an error status is parsed
+/// as the endpoint's error type and a body which doesn't match the response type fails.
+pub struct RecordingService {
+    status: reqwest::StatusCode,
+    body: String,
+    options: Options,
+    requests: Mutex<Vec<Request>>,
+}
+
+impl fmt::Debug for RecordingService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RecordingService {{ status: {} }}", self.status)
+    }
+}
+
+impl RecordingService {
+    /// Answer every request with `200 OK` and the json `body`
+    pub fn new(body: &str) -> Self {
+        RecordingService::responding(200, body)
+    }
+
+    /// Answer every request with the status and json `body`
+    ///
+    /// # Panics
+    ///
+    /// If `status` is not a valid http status code, i.e. outside of 100 to 999
+    pub fn responding(status: u16, body: &str) -> Self {
+        RecordingService {
+            status: reqwest::StatusCode::from_u16(status).expect("valid http status code"),
+            body: body.to_owned(),
+            options: Options::default(),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Record the request and process the canned response as the request's response
+    pub(crate) fn respond<TResponse, TError>(&self, req: Request) -> ResponseResult<TResponse, TError>
+    where
+        TResponse: serde::de::DeserializeOwned + fmt::Debug,
+        TError: serde::de::DeserializeOwned + fmt::Debug,
+    {
+        let expect = req.expectations();
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(req);
+        let result = process_response(
+            &self.options,
+            RECORDED_URL.to_owned(),
+            self.status,
+            expect,
+            reqwest::header::HeaderMap::new(),
+            Ok(self.body.to_owned()),
+        );
+        into_response_result(result)
+    }
+
+    /// Requests executed since they were last taken, in the order they were executed
+    pub fn take_requests(&self) -> Vec<Request> {
+        std::mem::take(&mut *self.requests.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Service for RecordingService {
+    type TRequestType = Request;
+    type TServiceError = Error;
+    type TErrorSerde = serde_json::Error;
+
+    fn exec<TRequest>(
+        &self,
+        req: TRequest,
+    ) -> ServiceResult<TRequest, Self::TServiceError, serde_json::Error>
+    where
+        TRequest: Into<Self::TRequestType> + Endpoint + fmt::Debug,
+    {
+        into_service_result(self.respond(req.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gateway::{Endpoint, Service, ServiceResult};
+
+    use super::RecordingService;
+    use crate::{Error, Request};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct CreateUser {
+        name: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct User {
+        id: u32,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct UserError {
+        reason: String,
+    }
+
+    impl From<CreateUser> for Request {
+        fn from(src: CreateUser) -> Request {
+            Request::Post {
+                path: "users".to_owned(),
+                body: serde_json::to_string(&src).unwrap(),
+            }
+        }
+    }
+
+    impl Endpoint for CreateUser {
+        type TResponse = User;
+        type TError = UserError;
+    }
+
+    #[test]
+    fn record_requests_without_sending() {
+        let svc = RecordingService::new(r#"{"id":7}"#);
+
+        match svc.exec(CreateUser { name: "foo".to_owned() }) {
+            ServiceResult::Ok (result) => assert_eq!(7, result.id),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+
+        let requests = svc.take_requests();
+        match requests.as_slice() {
+            [Request::Post { path, body }] => {
+                assert_eq!("users", path);
+                assert_eq!(r#"{"name":"foo"}"#, body);
+            }
+            requests => panic!("expected the submitted post but was [{:?}]", requests),
+        }
+        assert!(svc.take_requests().is_empty());
+    }
+
+    #[test]
+    fn answer_with_canned_error_status() {
+        let svc = RecordingService::responding(409, r#"{"reason":"exists"}"#);
+
+        match svc.exec(CreateUser { name: "foo".to_owned() }) {
+            ServiceResult::Ok (result) => panic!("should have failed with the canned status but was [{:?}]", result),
+            ServiceResult::Err (Error::ResultFailed { status, .. }, api_error) => {
+                assert_eq!(409, status);
+                assert_eq!("exists", api_error.reason);
+            }
+            ServiceResult::Err (service_error, api_error) => panic!("expected ResultFailed but was [{:?}] with [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should have parsed the api error but failed with [{:?}] and [{:?}]", service_error, maybe_api_serde),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{encode_path, serialize_payload, Error, Request};
+
+/// Http method a `Route` is served with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+/// Endpoint which declares its http method and path where it is defined, so it can be executed
+/// with `exec_route` without a hand written `From<T> for Request` conversion
+///
+/// `{field}` placeholders in the path are filled from the endpoint's serialized fields of the
+/// same name, e.g. `users/{id}`, each percent-encoded as a single path segment so a value such
+/// as `a/b` can't change the route, while an empty, `.` or `..` value is rejected as it would
+/// resolve to another resource.  `POST`, `PUT` and `PATCH` routes send the whole endpoint
+/// serialized as their json body.
+pub trait Route: serde::Serialize {
+    const METHOD: Method;
+    /// Path template resolved against the service's base url
+    const PATH: &'static str;
+
+    /// Describe the endpoint as a request, failing with `Error::SerializeRequestFailed` if it
+    /// can't be serialized or has no usable value for a placeholder in the path
+    fn into_request(self) -> Result<Request, Error>
+    where
+        Self: Sized,
+    {
+        let serialize_failed = |serde_error| Error::SerializeRequestFailed {
+            serde_error,
+            context: std::any::type_name::<Self>(),
+        };
+        let fields = serde_json::to_value(&self).map_err(serialize_failed)?;
+        let path = fill_path(Self::PATH, &fields).map_err(serialize_failed)?;
+        Ok(match Self::METHOD {
+            Method::Get => Request::Get { path },
+            Method::Delete => Request::Delete { path },
+            Method::Post => Request::Post {
+                path,
+                body: serialize_payload(&fields)?,
+            },
+            Method::Put => Request::Put {
+                path,
+                body: serialize_payload(&fields)?,
+            },
+            Method::Patch => Request::Patch {
+                path,
+                body: serialize_payload(&fields)?,
+            },
+        })
+    }
+}
+
+/// Replace each `{field}` in the template with the field's value encoded as a path segment,
+/// failing for a field which is missing or `null` or whose value isn't a segment of its own
+fn fill_path(template: &str, fields: &serde_json::Value) -> Result<String, serde_json::Error> {
+    let mut path = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        path.push_str(&rest[..start]);
+        let field = &rest[start + 1..end];
+        let value = match &fields[field] {
+            serde_json::Value::String(value) => value.to_owned(),
+            serde_json::Value::Null => {
+                let message = format!("no value for path placeholder `{{{}}}`", field);
+                return Err(serde::ser::Error::custom(message));
+            }
+            value => value.to_string(),
+        };
+        if value.is_empty() || value == "." || value == ".." {
+            let message = format!("`{}` can't fill path placeholder `{{{}}}`", value, field);
+            return Err(serde::ser::Error::custom(message));
+        }
+        match encode_path(&[value]) {
+            Ok(segment) => path.push_str(&segment),
+            Err(err) => return Err(serde::ser::Error::custom(err)),
+        }
+        rest = &rest[end + 1..];
+    }
+    path.push_str(rest);
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use gateway::{Endpoint, ServiceResult};
+    use mockito::mock;
+    #[cfg(feature = "blocking")]
+    use mockito::Matcher;
+
+    use super::{Method, Route};
+    use crate::{Error, Request};
+    #[cfg(feature = "async")]
+    use crate::AsyncReqwestJsonService;
+    #[cfg(feature = "blocking")]
+    use crate::ReqwestJsonService;
+
+    fn init() {
+        crate::testing::init();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct User {
+        id: u32,
+        name: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct CreateUser {
+        name: String,
+    }
+
+    impl Route for CreateUser {
+        const METHOD: Method = Method::Post;
+        const PATH: &'static str = "users";
+    }
+
+    impl Endpoint for CreateUser {
+        type TResponse = User;
+        type TError = ();
+    }
+
+    #[derive(Debug, Serialize)]
+    struct FetchUser {
+        id: u32,
+    }
+
+    impl Route for FetchUser {
+        const METHOD: Method = Method::Get;
+        const PATH: &'static str = "users/{id}";
+    }
+
+    impl Endpoint for FetchUser {
+        type TResponse = User;
+        type TError = ();
+    }
+
+    #[derive(Debug, Serialize)]
+    struct FetchFile {
+        name: String,
+    }
+
+    impl Route for FetchFile {
+        const METHOD: Method = Method::Get;
+        const PATH: &'static str = "files/{name}/meta";
+    }
+
+    #[derive(Debug, Serialize)]
+    struct FetchOwner {
+        id: u32,
+    }
+
+    impl Route for FetchOwner {
+        const METHOD: Method = Method::Get;
+        const PATH: &'static str = "owners/{owner_id}";
+    }
+
+    #[test]
+    fn encode_route_fields_as_path_segments() {
+        match (FetchFile { name: "a/b?c#d".to_owned() }).into_request() {
+            Ok(Request::Get { path }) => assert_eq!("files/a%2Fb%3Fc%23d/meta", path),
+            result => panic!("expected a get request but was [{:?}]", result),
+        }
+    }
+
+    #[test]
+    fn reject_route_without_placeholder_field() {
+        match (FetchOwner { id: 7 }).into_request() {
+            Err(Error::SerializeRequestFailed { context, .. }) => assert!(context.ends_with("FetchOwner"), "unexpected context [{}]", context),
+            result => panic!("expected SerializeRequestFailed but was [{:?}]", result),
+        }
+    }
+
+    #[test]
+    fn reject_route_field_which_is_not_a_segment() {
+        for name in &["", ".", ".."] {
+            match (FetchFile { name: name.to_string() }).into_request() {
+                Err(Error::SerializeRequestFailed { context, .. }) => assert!(context.ends_with("FetchFile"), "unexpected context [{}]", context),
+                result => panic!("expected SerializeRequestFailed for [{}] but was [{:?}]", name, result),
+            }
+        }
+    }
+
+    #[test]
+    fn describe_routes_as_requests() {
+        match (FetchUser { id: 7 }).into_request() {
+            Ok(Request::Get { path }) => assert_eq!("users/7", path),
+            result => panic!("expected a get request but was [{:?}]", result),
+        }
+        match (CreateUser { name: "foo".to_owned() }).into_request() {
+            Ok(Request::Post { path, body }) => {
+                assert_eq!("users", path);
+                assert_eq!(r#"{"name":"foo"}"#, body);
+            }
+            result => panic!("expected a post request but was [{:?}]", result),
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn exec_post_route() {
+        init();
+        let mock = mock("POST", "/exec_post_route/users")
+            .match_body(Matcher::Json(serde_json::json!({"name": "foo"})))
+            .with_status(201)
+            .with_body(r#"{"id":7,"name":"foo"}"#)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::with_url("http://www.foo.net/exec_post_route/").unwrap();
+
+        match svc.exec_route(CreateUser { name: "foo".to_owned() }) {
+            ServiceResult::Ok (result) => assert_eq!(7, result.id),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn exec_get_route_async() {
+        init();
+        let mock = mock("GET", "/exec_get_route_async/users/7")
+            .with_status(200)
+            .with_body(r#"{"id":7,"name":"foo"}"#)
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::with_url("http://www.foo.net/exec_get_route_async/")
+            .unwrap();
+
+        match svc.exec_route(FetchUser { id: 7 }).await {
+            ServiceResult::Ok (result) => assert_eq!("foo", result.name),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+}
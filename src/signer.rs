@@ -0,0 +1,159 @@
+use reqwest::header::HeaderMap;
+
+use crate::{parse_header, Body, Error, Prepared};
+
+/// Computes headers which authenticate a request from its contents right before it is sent,
+/// e.g. an AWS SigV4 style `Authorization` header over a digest of the request
+///
+/// The signer sees the request's own headers, including `Accept` and `Content-Type`, but not
+/// the client's default headers or the auth configured on the builder.  Multipart and streamed
+/// bodies are not available to sign, so the body is `None` for those as well as without one.
+pub trait Signer: Send + Sync {
+    /// Headers to add to the request, replacing any of the same name
+    fn sign(
+        &self,
+        method: &reqwest::Method,
+        url: &url::Url,
+        headers: &HeaderMap,
+        body: Option<&[u8]>,
+    ) -> Vec<(String, String)>;
+}
+
+/// Add the headers computed by the signer to the prepared request's headers, failing with
+/// `Error::InvalidHeaderName` or `Error::InvalidHeaderValue` if the signer returns invalid ones
+pub(crate) fn sign_request(
+    signer: &dyn Signer,
+    prepared: &Prepared,
+    mut headers: HeaderMap,
+) -> Result<HeaderMap, Error> {
+    let body = match &prepared.body {
+        Some(Body::Bytes(body)) => Some(body.as_slice()),
+        _ => None,
+    };
+    let signature = signer.sign(&prepared.method, &prepared.full_url(), &headers, body);
+    for (name, value) in signature {
+        let (name, value) = parse_header(&name, &value)?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "blocking")]
+    use gateway::Service;
+    use gateway::{Endpoint, ServiceResult};
+    use mockito::mock;
+    use reqwest::header::HeaderMap;
+
+    use super::Signer;
+    #[cfg(feature = "async")]
+    use crate::AsyncReqwestJsonService;
+    #[cfg(feature = "blocking")]
+    use crate::ReqwestJsonService;
+    use crate::Request;
+
+    fn init() {
+        crate::testing::init();
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Order {
+        id: u32,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct PlaceOrder {
+        item: String,
+    }
+
+    impl From<PlaceOrder> for Request {
+        fn from(src: PlaceOrder) -> Request {
+            Request::Post {
+                path: "orders".to_owned(),
+                body: serde_json::to_string(&src).unwrap(),
+            }
+        }
+    }
+
+    impl Endpoint for PlaceOrder {
+        type TResponse = Order;
+        type TError = ();
+    }
+
+    /// Signs with the method, path and body length, so the signature is predictable
+    struct Describe;
+
+    impl Signer for Describe {
+        fn sign(
+            &self,
+            method: &reqwest::Method,
+            url: &url::Url,
+            headers: &HeaderMap,
+            body: Option<&[u8]>,
+        ) -> Vec<(String, String)> {
+            let content_type = headers
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            let signature = format!(
+                "{} {} {} {}",
+                method,
+                url.path(),
+                content_type,
+                body.map(<[u8]>::len).unwrap_or_default()
+            );
+            vec![("X-Signature".to_owned(), signature)]
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn send_signature_headers() {
+        init();
+        let mock = mock("POST", "/send_signature_headers/orders")
+            .match_header("x-signature", "POST /send_signature_headers/orders application/json 14")
+            .with_status(201)
+            .with_body(r#"{"id":7}"#)
+            .expect(1)
+            .create();
+
+        let svc = ReqwestJsonService::builder()
+            .url("http://www.foo.net/send_signature_headers/")
+            .signer(Describe)
+            .build()
+            .unwrap();
+
+        match svc.exec(PlaceOrder { item: "foo".to_owned() }) {
+            ServiceResult::Ok (result) => assert_eq!(7, result.id),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn send_async_signature_headers() {
+        init();
+        let mock = mock("POST", "/send_async_signature_headers/orders")
+            .match_header("x-signature", "POST /send_async_signature_headers/orders application/json 14")
+            .with_status(201)
+            .with_body(r#"{"id":7}"#)
+            .expect(1)
+            .create();
+
+        let svc = AsyncReqwestJsonService::builder()
+            .url("http://www.foo.net/send_async_signature_headers/")
+            .signer(Describe)
+            .build_async()
+            .unwrap();
+
+        match svc.exec(PlaceOrder { item: "foo".to_owned() }).await {
+            ServiceResult::Ok (result) => assert_eq!(7, result.id),
+            ServiceResult::Err (service_error, api_error) => panic!("should not have failed with [{:?}] or had an api error to parse but was [{:?}]", service_error, api_error),
+            ServiceResult::Fail (service_error, maybe_api_serde) => panic!("should not have failed with [{:?}] or had an api error to parse but failed with [{:?}]", service_error, maybe_api_serde),
+        }
+        mock.assert();
+    }
+}
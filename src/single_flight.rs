@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "blocking")]
+use std::sync::Condvar;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+use crate::Error;
+
+/// Url, status, headers and body text of a response, as read by either service
+pub(crate) type Fetched = (String, StatusCode, HeaderMap, Result<String, Error>);
+
+/// Response shared with every caller of a coalesced request
+type Shared = (String, StatusCode, HeaderMap, String);
+
+/// Coalesces identical requests in flight at the same time, keyed by the request, so only the
+/// first is sent and the others share its response
+///
+/// Failures can't be shared, so callers waiting on a request which fails send their own.
+#[derive(Default)]
+pub(crate) struct SingleFlight {
+    calls: Mutex<HashMap<String, Arc<Call>>>,
+}
+
+/// Request in flight, which callers wait on until its response is published
+#[derive(Default)]
+struct Call {
+    /// `None` until the response is published, then the response if it could be shared
+    outcome: Mutex<Option<Option<Shared>>>,
+    #[cfg(feature = "blocking")]
+    landed: Condvar,
+    #[cfg(feature = "async")]
+    notify: tokio::sync::Notify,
+}
+
+impl Call {
+    fn outcome(&self) -> MutexGuard<'_, Option<Option<Shared>>> {
+        self.outcome.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Sends the request on behalf of every caller, publishing the response when dropped, so
+/// waiting callers aren't stranded should the sender panic or be cancelled
+struct Leader<'a> {
+    flights: &'a SingleFlight,
+    key: String,
+    call: Arc<Call>,
+    shared: Option<Shared>,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        let mut calls = self.flights.calls();
+        if calls.get(&self.key).map_or(false, |call| Arc::ptr_eq(call, &self.call)) {
+            calls.remove(&self.key);
+        }
+        drop(calls);
+        *self.call.outcome() = Some(self.shared.take());
+        #[cfg(feature = "blocking")]
+        self.call.landed.notify_all();
+        #[cfg(feature = "async")]
+        self.call.notify.notify_waiters();
+    }
+}
+
+enum Flight<'a> {
+    Lead(Leader<'a>),
+    Follow(Arc<Call>),
+}
+
+impl SingleFlight {
+    fn calls(&self) -> MutexGuard<'_, HashMap<String, Arc<Call>>> {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wait on the request in flight for the key, or become the one sending it
+    fn join(&self, key: String) -> Flight<'_> {
+        let mut calls = self.calls();
+        if let Some(call) = calls.get(&key) {
+            return Flight::Follow(call.clone());
+        }
+        let call = Arc::new(Call::default());
+        calls.insert(key.clone(), call.clone());
+        Flight::Lead(Leader {
+            flights: self,
+            key,
+            call,
+            shared: None,
+        })
+    }
+
+    /// Fetch the response for the key unless an identical request is already in flight, in which
+    /// case its response is shared
+    #[cfg(feature = "blocking")]
+    pub(crate) fn share<F>(&self, key: String, fetch: F) -> Result<Fetched, Error>
+    where
+        F: FnOnce() -> Result<Fetched, Error>,
+    {
+        match self.join(key) {
+            Flight::Lead(mut leader) => {
+                let fetched = fetch();
+                leader.shared = shareable(&fetched);
+                fetched
+            }
+            Flight::Follow(call) => {
+                let mut outcome = call.outcome();
+                while outcome.is_none() {
+                    outcome = call.landed.wait(outcome).unwrap_or_else(PoisonError::into_inner);
+                }
+                match outcome.clone().flatten() {
+                    Some((url, status, headers, text)) => Ok((url, status, headers, Ok(text))),
+                    None => {
+                        drop(outcome);
+                        fetch()
+                    }
+                }
+            }
+        }
+    }
+
+    /// Non-blocking equivalent of `share`
+    #[cfg(feature = "async")]
+    pub(crate) async fn share_async<F, Fut>(&self, key: String, fetch: F) -> Result<Fetched, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Fetched, Error>>,
+    {
+        match self.join(key) {
+            Flight::Lead(mut leader) => {
+                let fetched = fetch().await;
+                leader.shared = shareable(&fetched);
+                fetched
+            }
+            Flight::Follow(call) => {
+                loop {
+                    // Registered before checking so a response published in between isn't missed
+                    let landed = call.notify.notified();
+                    let outcome = call.outcome().clone();
+                    if let Some(outcome) = outcome {
+                        break match outcome {
+                            Some((url, status, headers, text)) => Ok((url, status, headers, Ok(text))),
+                            None => fetch().await,
+                        };
+                    }
+                    landed.await;
+                }
+            }
+        }
+    }
+}
+
+/// Response to publish to waiting callers, if it was read successfully
+fn shareable(fetched: &Result<Fetched, Error>) -> Option<Shared> {
+    match fetched {
+        Ok((url, status, headers, Ok(text))) => {
+            Some((url.to_owned(), *status, headers.clone(), text.to_owned()))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::{Mutex, Once, PoisonError};
+
+/// Records logged by the crate during tests, as their target, level and message
+static RECORDS: Mutex<Vec<(String, log::Level, String)>> = Mutex::new(Vec::new());
+
+/// Logs through env_logger as usual while capturing the crate's own records, so tests can assert
+/// what was logged
+struct Capture(env_logger::Logger);
+
+impl log::Log for Capture {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.target().starts_with(crate::LOG_TARGET) {
+            RECORDS.lock().unwrap_or_else(PoisonError::into_inner).push((
+                record.target().to_owned(),
+                record.level(),
+                record.args().to_string(),
+            ));
+        }
+        self.0.log(record);
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+/// Install the capturing logger once for all of the tests
+pub(crate) fn init() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let logger = env_logger::builder().is_test(true).build();
+        if log::set_boxed_logger(Box::new(Capture(logger))).is_ok() {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+    });
+}
+
+/// Target and level of the captured records whose message contains the text
+pub(crate) fn captured(containing: &str) -> Vec<(String, log::Level)> {
+    RECORDS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .filter(|(_, _, message)| message.contains(containing))
+        .map(|(target, level, _)| (target.to_owned(), *level))
+        .collect()
+}